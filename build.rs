@@ -11,6 +11,6 @@ fn main() {
         Path::new(&out_dir).join("packed.png"),
         Path::new(&out_dir).join("image.rs"),
         1000, 1500,
-        Some("Clone, Copy")
+        Some("Clone, Copy, serde::Deserialize")
     ).unwrap();
 }
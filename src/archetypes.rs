@@ -0,0 +1,144 @@
+use specs::world::{Builder, LazyBuilder};
+use cgmath::Vector2;
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::HashMap;
+use crate::components::*;
+use crate::resources::Difficulty;
+use crate::graphics::Image as GraphicsImage;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Movement {
+    TargetPlayer { speed: f32 },
+    // Move down the screen, stop to fire, then leave.
+    FiringMove { speed: f32, stop_after: f32, return_after: f32 },
+    Velocity { x: f32, y: f32 },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+    AtPlayer { num_bullets: u16, spread: f32 },
+    Circle { sides: u16, rotation_per_fire: f32 },
+}
+
+#[derive(Deserialize)]
+pub struct Attack {
+    pattern: Pattern,
+    cooldown: f32,
+    // Time after spawning before the first shot, plus a random amount up to `jitter`.
+    #[serde(default)]
+    delay: f32,
+    #[serde(default)]
+    jitter: f32,
+    bullet: GraphicsImage,
+    speed: f32,
+}
+
+impl Attack {
+    fn fires_bullets(&self, start: f32) -> FiresBullets {
+        let jitter = if self.jitter > 0.0 { rand::thread_rng().gen_range(0.0, self.jitter) } else { 0.0 };
+        let ready_at = start + self.delay + jitter;
+        let cooldown = Cooldown::ready_at(self.cooldown, ready_at);
+        let setup = BulletSetup { image: Image::from(self.bullet), speed: self.speed, colour: None };
+
+        match self.pattern {
+            Pattern::AtPlayer { num_bullets, spread } => FiresBullets::AtPlayer { num_bullets, spread, cooldown, setup },
+            Pattern::Circle { sides, rotation_per_fire } => FiresBullets::Circle { sides, rotation_per_fire, rotation: 0.0, cooldown, setup },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SpawnerData {
+    child: String,
+    cooldown: f32,
+    max_children: usize,
+    #[serde(default)]
+    release_on_death: bool,
+}
+
+#[derive(Deserialize)]
+pub struct Archetype {
+    image: GraphicsImage,
+    health: u32,
+    hitbox: [f32; 2],
+    movement: Option<Movement>,
+    attack: Option<Attack>,
+    #[serde(default)]
+    drops: DropTable,
+    // Points given to the players when killed.
+    #[serde(default)]
+    score: u32,
+    spawner: Option<SpawnerData>,
+}
+
+impl Archetype {
+    pub fn spawn<'a>(&self, builder: LazyBuilder<'a>, position: Vector2<f32>, start: f32, difficulty: Difficulty) -> LazyBuilder<'a> {
+        let builder = self.build(builder, position, start, difficulty);
+
+        match self.movement {
+            Some(Movement::TargetPlayer { speed }) => builder.with(TargetPlayer(speed)),
+            Some(Movement::FiringMove { speed, stop_after, return_after }) => builder.with(FiringMove {
+                speed, stop_time: start + stop_after, return_time: start + return_after,
+            }),
+            Some(Movement::Velocity { x, y }) => builder.with(Velocity(Vector2::new(x, y))),
+            None => builder,
+        }
+    }
+
+    // Enemies following a path ignore the archetype's movement.
+    pub fn spawn_on_path<'a>(&self, builder: LazyBuilder<'a>, curve: FollowCurve, start: f32, difficulty: Difficulty) -> LazyBuilder<'a> {
+        self.build(builder, curve.start(), start, difficulty).with(curve)
+    }
+
+    fn build<'a>(&self, builder: LazyBuilder<'a>, position: Vector2<f32>, start: f32, difficulty: Difficulty) -> LazyBuilder<'a> {
+        let mut builder = builder
+            .with(Position(position))
+            .with(FrozenUntil(start))
+            .with(DieOffscreen)
+            .with(Enemy)
+            .with(Health(difficulty.health(self.health)))
+            .with(Image::from(self.image))
+            .with(Hitbox(Vector2::new(self.hitbox[0], self.hitbox[1])))
+            .with(self.drops.clone())
+            .with(ScoreValue(self.score));
+
+        if let Some(attack) = &self.attack {
+            builder = builder.with(attack.fires_bullets(start));
+        }
+
+        if let Some(spawner) = &self.spawner {
+            builder = builder.with(Spawner {
+                child: spawner.child.clone(),
+                cooldown: Cooldown::ready_at(spawner.cooldown, start + spawner.cooldown),
+                max_children: spawner.max_children,
+                release_on_death: spawner.release_on_death,
+            });
+        }
+
+        builder
+    }
+}
+
+#[derive(Default)]
+pub struct Archetypes(HashMap<String, Archetype>);
+
+impl Archetypes {
+    pub fn load() -> Self {
+        let archetypes: HashMap<String, Archetype> = toml::from_str(include_str!("archetypes.toml")).unwrap();
+
+        for (name, archetype) in &archetypes {
+            if let Some(spawner) = &archetype.spawner {
+                assert!(archetypes.contains_key(&spawner.child), "'{}' spawns unknown archetype '{}'", name, spawner.child);
+            }
+        }
+
+        Self(archetypes)
+    }
+
+    pub fn get(&self, name: &str) -> &Archetype {
+        self.0.get(name).unwrap_or_else(|| panic!("Unknown enemy archetype '{}'", name))
+    }
+}
@@ -0,0 +1,478 @@
+use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use crate::resources::{Mode, Settings, Stage};
+
+// Sounds beyond this steal the voice of the sound that has been playing longest.
+const MAX_VOICES: usize = 16;
+// How loud the music is while paused.
+const DUCKED_VOLUME: f32 = 0.3;
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+type Music = Box<dyn Iterator<Item = f32> + Send>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Sound {
+    Shot,
+    Hit,
+    Explosion,
+    Graze,
+    Bomb,
+    Pickup,
+    MenuMove,
+}
+
+impl Sound {
+    const ALL: [Sound; 7] = [Sound::Shot, Sound::Hit, Sound::Explosion, Sound::Graze, Sound::Bomb, Sound::Pickup, Sound::MenuMove];
+
+    // Sound effects are synthesized instead of being loaded from files.
+    fn generate(self, sample_rate: u32) -> Vec<f32> {
+        let hiss = |t: f32, frequency: f32| noise((t * frequency) as u32);
+
+        match self {
+            Self::Shot => synthesize(0.04, sample_rate, |t, fade| square(1320.0, t) * fade * 0.1),
+            Self::Hit => synthesize(0.3, sample_rate, |t, fade| (sine(110.0, t) * 0.6 + hiss(t, 8000.0) * 0.4) * fade * 0.5),
+            Self::Explosion => synthesize(0.35, sample_rate, |t, fade| hiss(t, 6000.0) * fade * fade * 0.4),
+            Self::Graze => synthesize(0.03, sample_rate, |t, fade| sine(2000.0, t) * fade * 0.15),
+            Self::Bomb => synthesize(1.0, sample_rate, |t, fade| (sine(600.0 - 500.0 * t, t) * 0.5 + hiss(t, 4000.0) * 0.3) * fade * 0.5),
+            Self::Pickup => synthesize(0.1, sample_rate, |t, fade| sine(if t < 0.05 { 880.0 } else { 1320.0 }, t) * fade * 0.2),
+            Self::MenuMove => synthesize(0.04, sample_rate, |t, fade| square(440.0, t) * fade * 0.1),
+        }
+    }
+}
+
+// Calls `sample` with the time and a fade from 1 to 0 over `duration`.
+fn synthesize(duration: f32, sample_rate: u32, sample: impl Fn(f32, f32) -> f32) -> Vec<f32> {
+    let count = (duration * sample_rate as f32) as usize;
+
+    (0 .. count)
+        .map(|i| {
+            let t = i as f32 / sample_rate as f32;
+            sample(t, 1.0 - t / duration)
+        })
+        .collect()
+}
+
+fn sine(frequency: f32, t: f32) -> f32 {
+    (t * frequency * std::f32::consts::PI * 2.0).sin()
+}
+
+fn square(frequency: f32, t: f32) -> f32 {
+    sine(frequency, t).signum()
+}
+
+// Deterministic white noise from an integer hash.
+fn noise(i: u32) -> f32 {
+    let mut x = i.wrapping_mul(0x9E37_79B9);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85EB_CA6B);
+    x ^= x >> 13;
+    x as f32 / std::u32::MAX as f32 * 2.0 - 1.0
+}
+
+struct Voice {
+    samples: Arc<[f32]>,
+    position: usize,
+}
+
+struct Mixer {
+    sample_rate: u32,
+    sounds: HashMap<Sound, Arc<[f32]>>,
+    voices: Vec<Voice>,
+    music: Option<Music>,
+    master_volume: f32,
+    music_volume: f32,
+    effects_volume: f32,
+    ducked: bool,
+    // Moves towards the ducked volume gradually to avoid clicks.
+    music_gain: f32,
+}
+
+impl Mixer {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            sounds: Sound::ALL.iter().map(|&sound| (sound, sound.generate(sample_rate).into())).collect(),
+            voices: Vec::with_capacity(MAX_VOICES),
+            music: None,
+            master_volume: 1.0,
+            music_volume: 1.0,
+            effects_volume: 1.0,
+            ducked: false,
+            music_gain: 1.0,
+        }
+    }
+
+    fn play(&mut self, sound: Sound) {
+        let voice = Voice { samples: self.sounds[&sound].clone(), position: 0 };
+
+        if self.voices.len() < MAX_VOICES {
+            self.voices.push(voice);
+        } else if let Some(oldest) = self.voices.iter_mut().max_by_key(|voice| voice.position) {
+            *oldest = voice;
+        }
+    }
+
+    // Fills `output` with mono samples.
+    fn mix(&mut self, output: &mut [f32]) {
+        let target_gain = if self.ducked { DUCKED_VOLUME } else { 1.0 };
+        // Takes a tenth of a second to fully duck
+        let gain_step = 10.0 / self.sample_rate as f32;
+
+        for sample in output.iter_mut() {
+            self.music_gain += (target_gain - self.music_gain).max(-gain_step).min(gain_step);
+
+            let music = self.music.as_mut().and_then(|music| music.next()).unwrap_or(0.0);
+
+            let mut effects = 0.0;
+            for voice in &mut self.voices {
+                if let Some(value) = voice.samples.get(voice.position) {
+                    effects += value;
+                    voice.position += 1;
+                }
+            }
+
+            let mixed = music * self.music_volume * self.music_gain + effects * self.effects_volume;
+            *sample = (mixed * self.master_volume).max(-1.0).min(1.0);
+        }
+
+        self.voices.retain(|voice| voice.position < voice.samples.len());
+    }
+}
+
+pub struct Audio {
+    mixer: Arc<Mutex<Mixer>>,
+    sample_rate: u32,
+    music: Option<Stage>,
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self::with_sample_rate(DEFAULT_SAMPLE_RATE)
+    }
+}
+
+impl Audio {
+    fn with_sample_rate(sample_rate: u32) -> Self {
+        Self {
+            mixer: Arc::new(Mutex::new(Mixer::new(sample_rate))),
+            sample_rate,
+            music: None,
+        }
+    }
+
+    // Plays through the default output device, falling back to the null output if there isn't one.
+    pub fn new() -> (Self, Output) {
+        #[cfg(feature = "audio")]
+        {
+            match device::start() {
+                Ok(started) => return started,
+                Err(error) => log::warn!("Failed to start audio output, continuing without sound: {}", error),
+            }
+        }
+
+        (Self::default(), Output::Null { sample_rate: DEFAULT_SAMPLE_RATE })
+    }
+
+    pub fn play(&self, sound: Sound) {
+        self.mixer.lock().unwrap().play(sound);
+    }
+
+    // Makes the next update start the music from the beginning, even if the stage is the same as before.
+    pub fn restart_music(&mut self) {
+        self.music = None;
+    }
+
+    // Picks the music for the current stage, ducks it while paused and applies the volume settings.
+    pub fn update(&mut self, mode: &Mode, settings: &Settings) {
+        let (stage, paused) = match *mode {
+            Mode::Playing { stage, .. } => (Some(stage), false),
+            Mode::Paused { stage, .. } => (Some(stage), true),
+            _ => (None, false),
+        };
+
+        // Opening the music reads from disk, so do it before locking the mixer to avoid stalling the output
+        let music = if stage != self.music {
+            self.music = stage;
+            Some(stage.and_then(|stage| open_music(stage, self.sample_rate)))
+        } else {
+            None
+        };
+
+        let mut mixer = self.mixer.lock().unwrap();
+
+        if let Some(music) = music {
+            mixer.music = music;
+        }
+
+        mixer.ducked = paused;
+        mixer.master_volume = settings.master_volume;
+        mixer.music_volume = settings.music_volume;
+        mixer.effects_volume = settings.effects_volume;
+    }
+}
+
+pub enum Output {
+    // Mixes and throws away a tick's worth of samples each update, so that sounds still finish playing.
+    Null { sample_rate: u32 },
+    #[cfg(feature = "audio")]
+    Device { _stream: cpal::Stream },
+}
+
+impl Output {
+    pub fn update(&self, audio: &Audio) {
+        if let Self::Null { sample_rate } = self {
+            let mut buffer = vec![0.0; *sample_rate as usize / 60];
+            audio.mixer.lock().unwrap().mix(&mut buffer);
+        }
+    }
+}
+
+// The music isn't part of the repository (see the readme), so a missing track just means playing without music.
+#[cfg(feature = "audio")]
+fn open_music(stage: Stage, sample_rate: u32) -> Option<Music> {
+    let path = match stage {
+        Stage::One => "music/stage_one.ogg",
+        Stage::Two => "music/stage_two.ogg",
+    };
+
+    if !std::path::Path::new(path).exists() {
+        log::debug!("No music at '{}'", path);
+        return None;
+    }
+
+    match ogg::OggMusic::open(path, sample_rate) {
+        Ok(music) => Some(Box::new(music)),
+        Err(error) => {
+            log::warn!("Failed to open '{}': {}", path, error);
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+fn open_music(_stage: Stage, _sample_rate: u32) -> Option<Music> {
+    None
+}
+
+#[cfg(feature = "audio")]
+mod device {
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+    use std::sync::{Arc, Mutex};
+    use super::{Audio, Mixer, Output};
+
+    pub fn start() -> Result<(Audio, Output), Box<dyn std::error::Error>> {
+        let device = cpal::default_host().default_output_device()
+            .ok_or("no output device available")?;
+        let config = device.default_output_config()?;
+
+        let audio = Audio::with_sample_rate(config.sample_rate().0);
+        let mixer = audio.mixer.clone();
+
+        let sample_format = config.sample_format();
+        let config = config.into();
+
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config, mixer)?,
+            cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config, mixer)?,
+            cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config, mixer)?,
+        };
+
+        stream.play()?;
+
+        Ok((audio, Output::Device { _stream: stream }))
+    }
+
+    fn build_stream<T: cpal::Sample>(device: &cpal::Device, config: &cpal::StreamConfig, mixer: Arc<Mutex<Mixer>>) -> Result<cpal::Stream, cpal::BuildStreamError> {
+        let channels = config.channels as usize;
+        let mut buffer = Vec::new();
+
+        device.build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                buffer.resize(data.len() / channels, 0.0);
+                mixer.lock().unwrap().mix(&mut buffer);
+
+                for (frame, sample) in data.chunks_mut(channels).zip(&buffer) {
+                    for output in frame {
+                        *output = T::from(sample);
+                    }
+                }
+            },
+            |error| log::error!("Audio output error: {}", error),
+        )
+    }
+}
+
+#[cfg(feature = "audio")]
+mod ogg {
+    use lewton::inside_ogg::OggStreamReader;
+    use std::fs::File;
+
+    // Decodes an ogg vorbis file a packet at a time, looping at the end.
+    pub struct OggMusic {
+        path: &'static str,
+        reader: OggStreamReader<File>,
+        packet: Vec<i16>,
+        position: usize,
+        // Source frames per output sample.
+        step: f32,
+        phase: f32,
+    }
+
+    impl OggMusic {
+        pub fn open(path: &'static str, sample_rate: u32) -> Result<Self, Box<dyn std::error::Error>> {
+            let reader = OggStreamReader::new(File::open(path)?)?;
+            let step = reader.ident_hdr.audio_sample_rate as f32 / sample_rate as f32;
+
+            Ok(Self { path, reader, packet: Vec::new(), position: 0, step, phase: 0.0 })
+        }
+    }
+
+    impl Iterator for OggMusic {
+        type Item = f32;
+
+        fn next(&mut self) -> Option<f32> {
+            let channels = self.reader.ident_hdr.audio_channels as usize;
+
+            self.phase += self.step;
+            while self.phase >= 1.0 {
+                self.phase -= 1.0;
+                self.position += channels;
+            }
+
+            let mut restarted = false;
+
+            while self.position + channels > self.packet.len() {
+                self.position = self.position.saturating_sub(self.packet.len());
+
+                match self.reader.read_dec_packet_itl() {
+                    Ok(Some(packet)) => self.packet = packet,
+                    Ok(None) if !restarted => {
+                        self.reader = OggStreamReader::new(File::open(self.path).ok()?).ok()?;
+                        self.packet.clear();
+                        restarted = true;
+                    },
+                    // A file with no audio in it, or a decoding error
+                    _ => return None,
+                }
+            }
+
+            let frame = &self.packet[self.position .. self.position + channels];
+            Some(frame.iter().map(|&sample| sample as f32 / std::i16::MAX as f32).sum::<f32>() / channels as f32)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::PlayingState;
+    use crate::components::BombType;
+
+    const SAMPLE_RATE: u32 = 1000;
+
+    fn mixer() -> Mixer {
+        Mixer::new(SAMPLE_RATE)
+    }
+
+    #[test]
+    fn silent_without_sounds() {
+        let mut buffer = vec![1.0; 100];
+        mixer().mix(&mut buffer);
+        assert!(buffer.iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn sounds_are_scaled_by_volume() {
+        let mut mixer = mixer();
+        mixer.master_volume = 0.5;
+        mixer.effects_volume = 0.5;
+        mixer.play(Sound::Hit);
+
+        let expected = Sound::Hit.generate(SAMPLE_RATE);
+        let mut buffer = vec![0.0; expected.len() + 10];
+        mixer.mix(&mut buffer);
+
+        for (mixed, sample) in buffer.iter().zip(&expected) {
+            assert!((mixed - sample * 0.25).abs() < 1e-6);
+        }
+        assert!(buffer[expected.len() ..].iter().all(|&sample| sample == 0.0));
+        assert!(mixer.voices.is_empty());
+    }
+
+    #[test]
+    fn output_is_clamped() {
+        let mut mixer = mixer();
+        mixer.music = Some(Box::new(std::iter::repeat(4.0)));
+
+        let mut buffer = vec![0.0; 10];
+        mixer.mix(&mut buffer);
+        assert!(buffer.iter().all(|&sample| sample == 1.0));
+    }
+
+    #[test]
+    fn music_is_ducked_gradually() {
+        let mut mixer = mixer();
+        mixer.music = Some(Box::new(std::iter::repeat(0.5)));
+        mixer.ducked = true;
+
+        let mut buffer = vec![0.0; SAMPLE_RATE as usize / 5];
+        mixer.mix(&mut buffer);
+
+        assert!(buffer[0] > 0.5 * DUCKED_VOLUME);
+        assert!(buffer.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!((buffer[buffer.len() - 1] - 0.5 * DUCKED_VOLUME).abs() < 1e-6);
+    }
+
+    #[test]
+    fn oldest_voice_is_stolen() {
+        let mut mixer = mixer();
+        for _ in 0 .. MAX_VOICES {
+            mixer.play(Sound::Bomb);
+        }
+        mixer.mix(&mut [0.0; 10]);
+        mixer.play(Sound::Shot);
+
+        assert_eq!(mixer.voices.len(), MAX_VOICES);
+        assert_eq!(mixer.voices.iter().filter(|voice| voice.position == 0).count(), 1);
+    }
+
+    #[test]
+    fn null_output_finishes_sounds() {
+        let audio = Audio::with_sample_rate(SAMPLE_RATE);
+        let output = Output::Null { sample_rate: SAMPLE_RATE };
+        audio.play(Sound::Shot);
+        audio.play(Sound::Bomb);
+
+        // The bomb sound lasts a second, a little over 60 updates at this sample rate
+        for _ in 0 .. 70 {
+            output.update(&audio);
+        }
+
+        assert!(audio.mixer.lock().unwrap().voices.is_empty());
+    }
+
+    #[test]
+    fn missing_music_is_silent() {
+        let mut audio = Audio::with_sample_rate(SAMPLE_RATE);
+        let mode = Mode::Playing { stage: Stage::One, state: PlayingState::Playing, multiplayer: false, bomb: BombType::Circle };
+        audio.update(&mode, &Settings::default());
+        audio.restart_music();
+        audio.update(&mode, &Settings::default());
+
+        let mut buffer = vec![1.0; 100];
+        audio.mixer.lock().unwrap().mix(&mut buffer);
+        assert!(buffer.iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn settings_are_applied() {
+        let mut audio = Audio::with_sample_rate(SAMPLE_RATE);
+        let settings = Settings { master_volume: 0.2, music_volume: 0.4, effects_volume: 0.6, ..Settings::default() };
+        audio.update(&Mode::MainMenu { selected: 0 }, &settings);
+
+        let mixer = audio.mixer.lock().unwrap();
+        assert_eq!((mixer.master_volume, mixer.music_volume, mixer.effects_volume), (0.2, 0.4, 0.6));
+        assert!(!mixer.ducked);
+    }
+}
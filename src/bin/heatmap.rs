@@ -0,0 +1,190 @@
+// Runs a stage with no player input and records where the enemy bullets go,
+// writing heatmaps and printing per-second stats as CSV.
+//
+// Usage: heatmap <one|two> [easy|normal|hard|lunatic] [seconds] [output directory]
+
+use specs::prelude::*;
+use hectic_rs::{Game, resources::*, components::*, stages::StageSetup};
+
+// The size of a heatmap cell, in pixels.
+const CELL: usize = 4;
+const COLUMNS: usize = 480 / CELL;
+const ROWS: usize = 640 / CELL;
+// A cell counts as covered if a bullet is close enough to hit a player standing in it.
+const PLAYER_RADIUS: f32 = 5.0;
+// How many seconds each of the windowed heatmaps covers.
+const WINDOW: u32 = 10;
+
+struct Grid(Vec<u32>);
+
+impl Grid {
+    fn new() -> Self {
+        Self(vec![0; COLUMNS * ROWS])
+    }
+
+    fn add(&mut self, other: &Grid) {
+        for (cell, value) in self.0.iter_mut().zip(&other.0) {
+            *cell += value;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.0.iter_mut().for_each(|cell| *cell = 0);
+    }
+
+    fn mark(&mut self, x: f32, y: f32) {
+        if x >= 0.0 && y >= 0.0 {
+            let (column, row) = (x as usize / CELL, y as usize / CELL);
+            if column < COLUMNS && row < ROWS {
+                self.0[row * COLUMNS + column] += 1;
+            }
+        }
+    }
+
+    // Marks every cell whose centre is within `radius` of a point.
+    fn mark_around(&mut self, x: f32, y: f32, radius: f32) {
+        let cells = (radius / CELL as f32).ceil() as i32;
+        let (column, row) = ((x / CELL as f32) as i32, (y / CELL as f32) as i32);
+
+        for row in row - cells ..= row + cells {
+            for column in column - cells ..= column + cells {
+                let centre_x = (column as f32 + 0.5) * CELL as f32;
+                let centre_y = (row as f32 + 0.5) * CELL as f32;
+
+                if (centre_x - x).powi(2) + (centre_y - y).powi(2) <= radius * radius {
+                    self.mark(centre_x, centre_y);
+                }
+            }
+        }
+    }
+
+    fn coverage(&self) -> f32 {
+        self.0.iter().filter(|&&cell| cell > 0).count() as f32 / self.0.len() as f32
+    }
+
+    // The narrowest of the widest gaps in each row that has bullets in it, in pixels.
+    fn minimum_safe_gap(&self) -> Option<usize> {
+        self.0.chunks(COLUMNS)
+            .filter(|row| row.iter().any(|&cell| cell > 0))
+            .map(|row| {
+                row.split(|&cell| cell > 0).map(|gap| gap.len()).max().unwrap_or(0) * CELL
+            })
+            .min()
+    }
+
+    fn save(&self, filename: &str) {
+        let max = self.0.iter().cloned().max().unwrap_or(0).max(1) as f32;
+
+        let image = image::RgbImage::from_fn(480, 640, |x, y| {
+            let value = self.0[(y as usize / CELL) * COLUMNS + x as usize / CELL] as f32 / max;
+            // Black to red to yellow to white
+            let channel = |start: f32| ((value * 3.0 - start).max(0.0).min(1.0) * 255.0) as u8;
+            image::Rgb([channel(0.0), channel(1.0), channel(2.0)])
+        });
+
+        if let Err(error) = image.save(filename) {
+            eprintln!("Failed to save {}: {}", filename, error);
+        }
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: heatmap <one|two> [easy|normal|hard|lunatic] [seconds] [output directory]");
+    std::process::exit(1);
+}
+
+fn playing(game: &Game) -> bool {
+    matches!(game.mode(), Mode::Playing { state: PlayingState::Playing, .. })
+}
+
+fn main() {
+    env_logger::init();
+
+    let mut args = std::env::args().skip(1);
+
+    let stage = match args.next().as_deref() {
+        Some("one") => Stage::One,
+        Some("two") => Stage::Two,
+        _ => usage(),
+    };
+    let difficulty = match args.next().as_deref() {
+        Some("easy") => Difficulty::Easy,
+        Some("normal") | None => Difficulty::Normal,
+        Some("hard") => Difficulty::Hard,
+        Some("lunatic") => Difficulty::Lunatic,
+        _ => usage(),
+    };
+    let seconds: u32 = args.next().map(|seconds| seconds.parse().expect("Invalid number of seconds")).unwrap_or(120);
+    let directory = args.next().unwrap_or_else(|| ".".into());
+    let stage_name = if stage == Stage::One { "one" } else { "two" };
+    let prefix = format!("{}/heatmap-stage-{}-{}", directory, stage_name, format!("{:?}", difficulty).to_lowercase());
+
+    let mut game = Game::headless();
+    game.start_stage(stage, StageSetup { multiplayer: false, bomb: BombType::Circle, difficulty, start: StageStart::Time(0.0) });
+
+    // The player stands still and can't be hurt, so that the stage plays out in full.
+    {
+        let time = game.world.fetch::<GameTime>().total_time;
+        let player = game.world.read_storage::<Player>();
+        let mut invul = game.world.write_storage::<Invulnerability>();
+
+        for (_, invul) in (&player, &mut invul).join() {
+            invul.grant(time, f32::MAX);
+        }
+    }
+
+    let mut total = Grid::new();
+    let mut window = Grid::new();
+    let mut covered = Grid::new();
+
+    println!("second,mean bullets alive,mean coverage,minimum safe gap");
+
+    for second in 1 ..= seconds {
+        let (mut bullets_alive, mut coverage, mut minimum_gap) = (0, 0.0, None);
+
+        for _ in 0 .. 60 {
+            game.step();
+
+            if !playing(&game) {
+                break;
+            }
+
+            let position = game.world.read_storage::<Position>();
+            let bullet = game.world.read_storage::<CollidesWithBomb>();
+            covered.clear();
+
+            for (position, _) in (&position, &bullet).join() {
+                window.mark(position.0.x, position.0.y);
+                covered.mark_around(position.0.x, position.0.y, PLAYER_RADIUS);
+                bullets_alive += 1;
+            }
+
+            coverage += covered.coverage();
+            minimum_gap = match (minimum_gap, covered.minimum_safe_gap()) {
+                (Some(a), Some(b)) => Some(std::cmp::min(a, b)),
+                (a, b) => a.or(b),
+            };
+        }
+
+        println!(
+            "{},{:.1},{:.4},{}",
+            second, bullets_alive as f32 / 60.0, coverage / 60.0,
+            minimum_gap.map(|gap| gap.to_string()).unwrap_or_default(),
+        );
+
+        let ended = !playing(&game);
+
+        if second % WINDOW == 0 || second == seconds || ended {
+            window.save(&format!("{}-{:03}s.png", prefix, second));
+            total.add(&window);
+            window.clear();
+        }
+
+        if ended {
+            eprintln!("The stage ended after {} seconds", second);
+            break;
+        }
+    }
+
+    total.save(&format!("{}.png", prefix));
+}
@@ -1,9 +1,12 @@
 use cgmath::Vector2;
 use specs::*;
-use cgmath::MetricSpace;
+use cgmath::{MetricSpace, InnerSpace};
 use rand::{Rng, rngs::ThreadRng};
 use palette::IntoColor;
 
+use std::sync::Arc;
+use serde::Deserialize;
+
 use crate::{WIDTH, HEIGHT};
 use crate::graphics::Image as GraphicsImage;
 
@@ -61,12 +64,172 @@ pub struct BeenOnscreen;
 #[derive(Component)]
 pub struct FrozenUntil(pub f32);
 
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub enum BombType {
+    Circle,
+    Flash,
+    Beams,
+    Spirits,
+    Shield,
+}
+
+impl BombType {
+    pub const ALL: [BombType; 5] = [BombType::Circle, BombType::Flash, BombType::Beams, BombType::Spirits, BombType::Shield];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|bomb| *bomb == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    // How long the bomb stays active for, in seconds.
+    pub fn duration(self) -> f32 {
+        match self {
+            // The time it takes the circle to expand over the whole screen.
+            Self::Circle => 100.0 / 60.0,
+            Self::Flash => 0.5,
+            Self::Beams => 3.0,
+            Self::Spirits => 4.0,
+            Self::Shield => 6.0,
+        }
+    }
+
+    // How long the player that used the bomb can't be damaged for.
+    pub fn invulnerability(self) -> f32 {
+        match self {
+            Self::Circle => 2.0,
+            Self::Flash => 1.5,
+            Self::Beams => 3.5,
+            Self::Spirits => 2.0,
+            Self::Shield => 6.0,
+        }
+    }
+
+    // Damage dealt every `Bomb::DAMAGE_COOLDOWN` seconds to enemies that are covered by the bomb.
+    pub fn damage(self) -> u32 {
+        match self {
+            Self::Circle => 4,
+            Self::Flash => 10,
+            Self::Beams => 3,
+            Self::Spirits => 6,
+            Self::Shield => 2,
+        }
+    }
+
+    pub fn boss_damage(self) -> u32 {
+        match self {
+            Self::Circle => 2,
+            Self::Flash => 3,
+            Self::Beams => 2,
+            Self::Spirits => 4,
+            Self::Shield => 1,
+        }
+    }
+
+    // Score given to the bomb owner for each bullet the bomb removes.
+    pub fn bullet_score(self) -> u32 {
+        match self {
+            Self::Circle | Self::Beams => 10,
+            Self::Flash => 5,
+            Self::Spirits | Self::Shield => 20,
+        }
+    }
+}
+
+pub enum BombKind {
+    Circle { radius: f32 },
+    Flash,
+    Beams { rotation: f32 },
+    Spirit { velocity: Vector2<f32> },
+    Shield,
+}
+
 #[derive(Component)]
-pub struct Circle { pub radius: f32 } 
+pub struct Bomb {
+    pub ty: BombType,
+    pub kind: BombKind,
+    pub owner: Entity,
+    pub started: f32,
+    pub damage_cooldown: Cooldown,
+}
+
+impl Bomb {
+    pub const DAMAGE_COOLDOWN: f32 = 0.25;
+    pub const NUM_BEAMS: u16 = 4;
+    pub const BEAM_WIDTH: f32 = 30.0;
+    // Long enough to cross the screen diagonally.
+    pub const BEAM_LENGTH: f32 = 800.0;
+    pub const BEAM_ROTATION_SPEED: f32 = 0.03;
+    pub const NUM_SPIRITS: u16 = 6;
+    pub const SPIRIT_RADIUS: f32 = 30.0;
+    pub const SPIRIT_SPEED: f32 = 6.0;
+    pub const SHIELD_RADIUS: f32 = 60.0;
+
+    fn new(ty: BombType, kind: BombKind, owner: Entity, time: f32) -> Self {
+        Self {
+            ty, kind, owner,
+            started: time,
+            damage_cooldown: Cooldown::new(Self::DAMAGE_COOLDOWN),
+        }
+    }
+
+    // A bomb of type `ty` can be made up of multiple entities, e.g. the spirits.
+    pub fn spawn(ty: BombType, owner: Entity, time: f32) -> Vec<Self> {
+        match ty {
+            BombType::Circle => vec![Self::new(ty, BombKind::Circle { radius: 0.0 }, owner, time)],
+            BombType::Flash => vec![Self::new(ty, BombKind::Flash, owner, time)],
+            BombType::Beams => vec![Self::new(ty, BombKind::Beams { rotation: 0.0 }, owner, time)],
+            BombType::Spirits => (0 .. Self::NUM_SPIRITS)
+                .map(|i| {
+                    let rotation = i as f32 / Self::NUM_SPIRITS as f32 * std::f32::consts::PI * 2.0;
+                    let velocity = Vector2::new(rotation.cos(), rotation.sin()) * Self::SPIRIT_SPEED;
+                    Self::new(ty, BombKind::Spirit { velocity }, owner, time)
+                })
+                .collect(),
+            BombType::Shield => vec![Self::new(ty, BombKind::Shield, owner, time)],
+        }
+    }
+
+    pub fn finished(&self, time: f32) -> bool {
+        self.started + self.ty.duration() <= time
+    }
+
+    // Whether the bomb follows the position of the player that used it.
+    pub fn follows_owner(&self) -> bool {
+        matches!(self.kind, BombKind::Beams { .. } | BombKind::Shield)
+    }
+
+    pub fn beam_rotations(rotation: f32) -> impl Iterator<Item = f32> {
+        (0 .. Self::NUM_BEAMS)
+            .map(move |i| rotation + i as f32 / Self::NUM_BEAMS as f32 * std::f32::consts::PI * 2.0)
+    }
+
+    pub fn covers(&self, bomb_pos: Vector2<f32>, pos: Vector2<f32>) -> bool {
+        match self.kind {
+            BombKind::Circle { radius } => pos.distance2(bomb_pos) <= radius.powi(2),
+            BombKind::Flash => true,
+            BombKind::Beams { rotation } => {
+                let offset = pos - bomb_pos;
+
+                Self::beam_rotations(rotation).any(|rotation| {
+                    let direction = Vector2::new(rotation.cos(), rotation.sin());
+                    let along = offset.x * direction.x + offset.y * direction.y;
+                    let across = offset.x * direction.y - offset.y * direction.x;
+                    along >= 0.0 && across.abs() <= Self::BEAM_WIDTH / 2.0
+                })
+            },
+            BombKind::Spirit { .. } => pos.distance2(bomb_pos) <= Self::SPIRIT_RADIUS.powi(2),
+            BombKind::Shield => pos.distance2(bomb_pos) <= Self::SHIELD_RADIUS.powi(2),
+        }
+    }
+}
 
 #[derive(Component)]
 pub struct CollidesWithBomb;
 
+// An enemy bullet that has already been grazed by a player.
+#[derive(Component)]
+pub struct Grazed;
+
 #[derive(Component)]
 pub struct Text {
     pub text: String,
@@ -87,20 +250,239 @@ impl Text {
 pub struct Boss {
     pub move_timer: f32,
     pub current_move: usize,
+    pub phase_timer: f32,
+    pub current_phase: usize,
+    pub invulnerable_until: f32,
     pub max_health: u32,
-    pub moves: Vec<BossMove>,
+    pub phases: Vec<BossPhase>,
+    move_state: Option<BossMoveState>,
 }
 
 impl Boss {
+    // How long the boss can't be damaged for after changing phases.
+    pub const PHASE_INVULNERABILITY: f32 = 2.0;
+    pub const SPEED: f32 = 10.0 / 3.0;
+
+    pub fn new(max_health: u32, phases: Vec<BossPhase>) -> Self {
+        Self {
+            move_timer: 0.0,
+            current_move: 0,
+            phase_timer: 0.0,
+            current_phase: 0,
+            invulnerable_until: std::f32::MIN,
+            max_health,
+            phases,
+            move_state: None,
+        }
+    }
+
+    pub fn phase(&self) -> &BossPhase {
+        &self.phases[self.current_phase]
+    }
+
     pub fn current_move(&self) -> &BossMove {
-        &self.moves[self.current_move]
+        &self.phase().moves[self.current_move]
+    }
+
+    pub fn next_move(&mut self) {
+        self.move_timer = 0.0;
+        self.move_state = None;
+        self.current_move = (self.current_move + 1) % self.phase().moves.len();
+    }
+
+    // Returns the boss' new position and whether it has finished moving.
+    pub fn step_movement(&mut self, pos: Vector2<f32>, player_x: f32) -> (Vector2<f32>, bool) {
+        let movement = &self.phases[self.current_phase].moves[self.current_move].movement;
+        let state = self.move_state.get_or_insert_with(|| BossMoveState::new(pos, movement));
+        state.elapsed += 1.0 / 60.0;
+
+        match movement {
+            BossMovement::To(position) => step_towards(pos, *position, Self::SPEED),
+            BossMovement::Eased { position, time, easing } => {
+                let t = (state.elapsed / *time).min(1.0);
+                (state.start + (*position - state.start) * easing.apply(t), t >= 1.0)
+            },
+            BossMovement::Curve(_) => {
+                let curve = state.curve.as_mut().unwrap();
+
+                if !state.on_path {
+                    let (pos, reached) = step_towards(pos, curve.start(), Self::SPEED);
+                    state.on_path = reached;
+                    (pos, false)
+                } else if curve.finished() {
+                    (pos, true)
+                } else {
+                    (curve.step(), false)
+                }
+            },
+            BossMovement::Orbit { center, radius, speed } => {
+                state.angle += *speed / 60.0;
+                let target = *center + Vector2::new(state.angle.cos(), state.angle.sin()) * *radius;
+
+                if state.on_path {
+                    (target, true)
+                } else {
+                    let (pos, reached) = step_towards(pos, target, Self::SPEED * 2.0);
+                    state.on_path = reached;
+                    (pos, reached)
+                }
+            },
+            BossMovement::TrackPlayer { y, speed } => (step_towards(pos, Vector2::new(player_x, *y), *speed).0, true),
+        }
+    }
+
+    pub fn phases_remaining(&self) -> usize {
+        self.phases.len() - self.current_phase - 1
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.phase().time_limit().map(|duration| self.phase_timer >= duration).unwrap_or(false)
+    }
+
+    pub fn should_change_phase(&self, health: u32) -> bool {
+        self.phases_remaining() > 0 && (health <= self.phase().health_threshold || self.timed_out())
     }
+
+    pub fn change_phase(&mut self, time: f32) {
+        self.current_phase += 1;
+        self.current_move = 0;
+        self.move_timer = 0.0;
+        self.move_state = None;
+        self.phase_timer = 0.0;
+        self.invulnerable_until = time + Self::PHASE_INVULNERABILITY;
+    }
+
+    pub fn invulnerable(&self, time: f32) -> bool {
+        self.invulnerable_until > time
+    }
+
+    // For practicing a later phase straight away.
+    pub fn start_at_phase(&mut self, phase: usize) {
+        self.current_phase = phase.min(self.phases.len() - 1);
+    }
+
+    // The boss' health when the current phase began.
+    pub fn phase_start_health(&self) -> u32 {
+        match self.current_phase.checked_sub(1) {
+            Some(previous) => self.phases[previous].health_threshold,
+            None => self.max_health
+        }
+    }
+
+    // How much of the current phase's share of the boss' health is left.
+    pub fn phase_health_perc(&self, health: u32) -> f32 {
+        let start = self.phase_start_health();
+        let threshold = self.phase().health_threshold;
+
+        // A phase that timed out can leave the boss with more health than the next phase starts with
+        (health.saturating_sub(threshold) as f32 / start.saturating_sub(threshold).max(1) as f32).min(1.0)
+    }
+
+    // Bosses can't be damaged past the end of their current phase, so that no phase gets skipped.
+    pub fn clamp_damage(&self, health: u32, damage: u32) -> u32 {
+        let floor = if self.phases_remaining() > 0 { self.phase().health_threshold } else { 0 };
+        damage.min(health.saturating_sub(floor))
+    }
+}
+
+pub struct BossPhase {
+    pub moves: Vec<BossMove>,
+    // The phase ends when the boss' health drops to this.
+    pub health_threshold: u32,
+    // Or optionally, when the phase has gone on for this long. Spell cards always have their own duration instead.
+    pub duration: Option<f32>,
+    pub spell_card: Option<SpellCard>,
 }
 
+impl BossPhase {
+    pub fn time_limit(&self) -> Option<f32> {
+        self.spell_card.map(|card| card.duration).or(self.duration)
+    }
+}
+
+// A named boss attack. Beating it before its phase times out without getting hit or bombing awards the bonus.
+#[derive(Clone, Copy)]
+pub struct SpellCard {
+    pub name: &'static str,
+    pub bonus: u32,
+    pub duration: f32,
+}
+
+#[derive(Component)]
+pub struct SpellCardName;
+
 pub struct BossMove {
-    pub position: Vector2<f32>,
+    pub movement: BossMovement,
     pub duration: f32,
     pub fires: FiresBullets,
+    // Start firing (and counting down the duration) straight away instead of waiting to finish moving.
+    pub fire_while_moving: bool,
+}
+
+pub enum BossMovement {
+    To(Vector2<f32>),
+    Eased { position: Vector2<f32>, time: f32, easing: Easing },
+    Curve(FollowCurve),
+    // Circle around a point, at `speed` radians per second.
+    Orbit { center: Vector2<f32>, radius: f32, speed: f32 },
+    TrackPlayer { y: f32, speed: f32 },
+}
+
+#[derive(Clone, Copy)]
+pub enum Easing {
+    Linear,
+    InQuad,
+    OutQuad,
+    InOutQuad,
+    InOutSine,
+}
+
+impl Easing {
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Self::Linear => t,
+            Self::InQuad => t * t,
+            Self::OutQuad => t * (2.0 - t),
+            Self::InOutQuad => if t < 0.5 { 2.0 * t * t } else { -1.0 + (4.0 - 2.0 * t) * t },
+            Self::InOutSine => (1.0 - (t * std::f32::consts::PI).cos()) / 2.0,
+        }
+    }
+}
+
+// The progress of the boss along the current move's movement.
+pub struct BossMoveState {
+    start: Vector2<f32>,
+    elapsed: f32,
+    angle: f32,
+    curve: Option<FollowCurve>,
+    // Whether the boss has reached the curve or orbit it's meant to be following yet.
+    on_path: bool,
+}
+
+impl BossMoveState {
+    fn new(pos: Vector2<f32>, movement: &BossMovement) -> Self {
+        Self {
+            start: pos,
+            elapsed: 0.0,
+            angle: match movement {
+                BossMovement::Orbit { center, .. } => (pos.y - center.y).atan2(pos.x - center.x),
+                _ => 0.0
+            },
+            curve: match movement {
+                BossMovement::Curve(curve) => Some(curve.clone()),
+                _ => None
+            },
+            on_path: false,
+        }
+    }
+}
+
+pub fn step_towards(pos: Vector2<f32>, target: Vector2<f32>, speed: f32) -> (Vector2<f32>, bool) {
+    if pos.distance2(target) > speed.powi(2) {
+        (pos + (target - pos).normalize_to(speed), false)
+    } else {
+        (target, true)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -164,7 +546,12 @@ impl Cooldown {
     }
 
     pub fn is_ready(&mut self, time: f32) -> bool {
-        let is_ready = self.last_fired + self.cooldown_time <= time;
+        self.is_ready_scaled(time, 1.0)
+    }
+
+    // Like `is_ready`, but with the cooldown time multiplied by `scale`.
+    pub fn is_ready_scaled(&mut self, time: f32, scale: f32) -> bool {
+        let is_ready = self.last_fired + self.cooldown_time * scale <= time;
         if is_ready {
             self.last_fired = time;
         }
@@ -204,15 +591,66 @@ fn curve_point_scalar(a: f32, b: f32, c: f32, d: f32, t: f32) -> f32 {
 #[derive(Component)]
 pub struct Friendly;
 
+// The player that fired a bullet, so that its kills can be credited to them.
+#[derive(Component)]
+pub struct ShotBy(pub Entity);
+
 #[derive(Component)]
 pub struct Enemy;
 
+// A player whose controls are set by `BotControl` instead of the keyboard.
+#[derive(Component)]
+pub struct Bot;
+
 #[derive(Component)]
 pub struct Health(pub u32);
 
 #[derive(Component)]
 pub struct Hitbox(pub Vector2<f32>);
 
+#[derive(Clone, Deserialize)]
+pub struct WeightedItem {
+    pub item: Pickup,
+    pub weight: u32,
+}
+
+// What an enemy drops when killed.
+#[derive(Component, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DropTable {
+    // The chance of dropping one item picked from `items`.
+    pub chance: f32,
+    pub items: Vec<WeightedItem>,
+    // Items that are always dropped.
+    pub guaranteed: Vec<Pickup>,
+}
+
+impl DropTable {
+    pub fn roll(&self, rng: &mut ThreadRng) -> Vec<Pickup> {
+        let mut items = self.guaranteed.clone();
+        let total_weight: u32 = self.items.iter().map(|item| item.weight).sum();
+
+        if total_weight > 0 && rng.gen_range(0.0, 1.0) < self.chance {
+            let mut choice = rng.gen_range(0, total_weight);
+
+            for item in &self.items {
+                if choice < item.weight {
+                    items.push(item.item);
+                    break;
+                }
+
+                choice -= item.weight;
+            }
+        }
+
+        items
+    }
+}
+
+// Points awarded for killing an enemy.
+#[derive(Component)]
+pub struct ScoreValue(pub u32);
+
 #[derive(Component)]
 pub struct Explosion(pub f32);
 
@@ -226,26 +664,75 @@ impl Invulnerability {
 
     pub fn can_damage(&mut self, time: f32) -> bool {
         if self.time_remaining(time) <= 0.0 {
-            self.0 = time;
+            self.0 = time + 5.0;
             true
         } else {
             false
         }
     }
 
+    pub fn grant(&mut self, time: f32, duration: f32) {
+        self.0 = self.0.max(time + duration);
+    }
+
     pub fn time_remaining(&self, time: f32) -> f32 {
-        5.0 - (time - self.0)
+        self.0 - time
     }
 }
 
 #[derive(Component)]
-pub struct PowerOrb(pub u32);
+pub struct Score(pub u32);
+
+#[derive(Component, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pickup {
+    // Adds to the power bar.
+    Power(u32),
+    // Points, worth less the further below the point-of-collection line they are collected.
+    Points(u32),
+    Life,
+    // A fifth of a bomb.
+    BombPiece,
+}
+
+impl Pickup {
+    pub fn image(self) -> GraphicsImage {
+        match self {
+            Self::Power(value) if value >= 5 => GraphicsImage::BigOrb,
+            Self::Power(_) => GraphicsImage::Orb,
+            Self::Points(_) => GraphicsImage::Point,
+            Self::Life => GraphicsImage::Life,
+            Self::BombPiece => GraphicsImage::BombPiece,
+        }
+    }
+}
+
+// Releases enemies of the `child` archetype over time.
+#[derive(Component)]
+pub struct Spawner {
+    pub child: String,
+    pub cooldown: Cooldown,
+    // The most children that can be alive at once.
+    pub max_children: usize,
+    // Release enough children to reach `max_children` when killed.
+    pub release_on_death: bool,
+}
+
+#[derive(Component)]
+pub struct SpawnedBy(pub Entity);
+
+// An item that is flying towards a player.
+#[derive(Component)]
+pub struct Attracted(pub Entity);
 
 #[derive(Component)]
 pub struct PowerBar(pub u32);
 
 impl PowerBar {
     pub const FULL: u32 = 50;
+    pub const BOMB_COST: u32 = Self::FULL / 2;
+    // Bombing to cancel a pending hit costs more than a regular bomb.
+    pub const DEATH_BOMB_COST: u32 = Self::FULL * 3 / 4;
 
     pub fn add(&mut self, value: u32) {
         self.0 = (self.0 + value).min(Self::FULL)
@@ -255,9 +742,9 @@ impl PowerBar {
         self.0 as f32 / Self::FULL as f32
     }
 
-    pub fn empty(&mut self) -> bool {
-        if self.0 == Self::FULL {
-            self.0 = 0;
+    pub fn spend(&mut self, cost: u32) -> bool {
+        if self.0 >= cost {
+            self.0 -= cost;
             true
         } else {
             false
@@ -265,74 +752,219 @@ impl PowerBar {
     }
 }
 
+// A hit that has been taken but not yet applied. The player can cancel it by bombing before `expires`.
 #[derive(Component)]
+pub struct PendingHit { pub expires: f32 }
+
+// A path made up of spline segments, with the arc length precomputed so it can be followed at a constant speed.
+pub struct SplinePath {
+    // (distance along the path, point) pairs.
+    samples: Vec<(f32, Vector2<f32>)>,
+    // The distance along the path of each point where two segments meet, including the start and end.
+    waypoints: Vec<f32>,
+}
+
+impl SplinePath {
+    const SAMPLES_PER_SEGMENT: usize = 32;
+
+    // Every 4 consecutive points make a segment. The first and last points are only used as control points.
+    pub fn catmull_rom(points: &[Vector2<f32>]) -> Self {
+        assert!(points.len() >= 4, "Catmull-Rom paths need at least 4 points");
+
+        let segments: Vec<_> = points.windows(4)
+            .map(|window| [window[0], window[1], window[2], window[3]])
+            .collect();
+
+        Self::from_segments(&segments, |segment: &[Vector2<f32>; 4], t: f32| Vector2::new(
+            curve_point_scalar(segment[0].x, segment[1].x, segment[2].x, segment[3].x, t),
+            curve_point_scalar(segment[0].y, segment[1].y, segment[2].y, segment[3].y, t)
+        ))
+    }
+
+    // Cubic bezier segments, each sharing their last point with the start of the next segment.
+    pub fn bezier(points: &[Vector2<f32>]) -> Self {
+        assert!(points.len() >= 4 && (points.len() - 1) % 3 == 0, "Bezier paths need 3n + 1 points");
+
+        let segments: Vec<_> = (0 .. (points.len() - 1) / 3)
+            .map(|i| [points[i * 3], points[i * 3 + 1], points[i * 3 + 2], points[i * 3 + 3]])
+            .collect();
+
+        Self::from_segments(&segments, |segment: &[Vector2<f32>; 4], t: f32| {
+            let u = 1.0 - t;
+            segment[0] * (u * u * u) + segment[1] * (3.0 * u * u * t) + segment[2] * (3.0 * u * t * t) + segment[3] * (t * t * t)
+        })
+    }
+
+    fn from_segments(segments: &[[Vector2<f32>; 4]], point: fn(&[Vector2<f32>; 4], f32) -> Vector2<f32>) -> Self {
+        let mut samples = vec![(0.0, point(&segments[0], 0.0))];
+        let mut waypoints = vec![0.0];
+
+        for segment in segments {
+            for i in 1 ..= Self::SAMPLES_PER_SEGMENT {
+                let (distance, previous) = *samples.last().unwrap();
+                let next = point(segment, i as f32 / Self::SAMPLES_PER_SEGMENT as f32);
+                samples.push((distance + next.distance(previous), next));
+            }
+
+            waypoints.push(samples.last().unwrap().0);
+        }
+
+        // Keeps the lookup in `point_at` free of NaNs
+        assert!(
+            samples.iter().all(|(distance, point)| distance.is_finite() && point.x.is_finite() && point.y.is_finite()),
+            "Spline paths need finite points"
+        );
+
+        Self { samples, waypoints }
+    }
+
+    pub fn length(&self) -> f32 {
+        self.samples.last().unwrap().0
+    }
+
+    pub fn point_at(&self, distance: f32) -> Vector2<f32> {
+        let distance = distance.max(0.0);
+        let length = self.length();
+
+        // Carry on in a straight line past the end of the path
+        if distance >= length {
+            let (end_distance, end) = self.samples[self.samples.len() - 1];
+            let (before_distance, before) = self.samples[self.samples.len() - 2];
+
+            if end_distance > before_distance {
+                return end + (end - before) * ((distance - length) / (end_distance - before_distance));
+            } else {
+                return end;
+            }
+        }
+
+        // The samples are all finite, and `max` turns a NaN distance into 0.
+        match self.samples.binary_search_by(|(sample_distance, _)| sample_distance.partial_cmp(&distance).unwrap()) {
+            Ok(index) => self.samples[index].1,
+            Err(index) => {
+                let (distance_a, a) = self.samples[index - 1];
+                let (distance_b, b) = self.samples[index];
+                a + (b - a) * ((distance - distance_a) / (distance_b - distance_a))
+            }
+        }
+    }
+}
+
+// What to do once the end of a path has been reached.
+#[derive(Clone, Copy)]
+pub enum PathEnd {
+    // Keep going in a straight line.
+    Continue,
+    Despawn,
+    // Stop following the path and fly towards a player at the given speed.
+    TargetPlayer(f32),
+}
+
+#[derive(Component, Clone)]
 pub struct FollowCurve {
-    a: Vector2<f32>,
-    b: Vector2<f32>,
-    c: Vector2<f32>,
-    d: Vector2<f32>,
-    time: f32,
+    path: Arc<SplinePath>,
+    distance: f32,
     speed: f32,
+    pub on_end: PathEnd,
+    end_reported: bool,
+    // Added to every point on the path, so that formations can share one path.
+    offset: Vector2<f32>,
+    // (waypoint index, seconds to pause for), sorted by waypoint.
+    pauses: Vec<(usize, f32)>,
+    next_pause: usize,
+    paused_for: f32,
 }
 
 impl FollowCurve {
+    pub fn new(path: SplinePath, speed: f32) -> Self {
+        Self {
+            path: Arc::new(path),
+            distance: 0.0,
+            speed,
+            on_end: PathEnd::Continue,
+            end_reported: false,
+            offset: Vector2::new(0.0, 0.0),
+            pauses: Vec::new(),
+            next_pause: 0,
+            paused_for: 0.0,
+        }
+    }
+
+    pub fn with_pause(mut self, waypoint: usize, duration: f32) -> Self {
+        assert!(waypoint < self.path.waypoints.len());
+        self.pauses.push((waypoint, duration));
+        self.pauses.sort_by_key(|&(waypoint, _)| waypoint);
+        self
+    }
+
+    pub fn with_end(mut self, on_end: PathEnd) -> Self {
+        self.on_end = on_end;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: Vector2<f32>) -> Self {
+        self.offset = offset;
+        self
+    }
+
     pub fn start(&self) -> Vector2<f32> {
-        self.b
+        self.path.point_at(0.0) + self.offset
     }
 
-    fn point(&self, time: f32) -> Vector2<f32> {
-        Vector2::new(
-            curve_point_scalar(self.a.x, self.b.x, self.c.x, self.d.x, time),
-            curve_point_scalar(self.a.y, self.b.y, self.c.y, self.d.y, time)
-        )
+    pub fn finished(&self) -> bool {
+        self.distance >= self.path.length()
     }
 
-    pub fn step(&mut self, previous_point: Vector2<f32>) -> Vector2<f32> {
-        let mut min_time = self.time;
-        let mut max_time = self.time + 1.0;
+    // True only the first time it's called after the end of the path has been reached.
+    pub fn take_end(&mut self) -> bool {
+        let reached = self.finished() && !self.end_reported;
+        self.end_reported |= reached;
+        reached
+    }
+
+    pub fn step(&mut self) -> Vector2<f32> {
+        if self.paused_for > 0.0 {
+            self.paused_for -= 1.0 / 60.0;
+        } else {
+            let mut distance = self.distance + self.speed;
 
-        loop {
-            let mid_time = (min_time + max_time) / 2.0;
-            let mid_point = self.point(mid_time);
-            let mid_dist = mid_point.distance(previous_point);
+            if let Some(&(waypoint, duration)) = self.pauses.get(self.next_pause) {
+                let waypoint_distance = self.path.waypoints[waypoint];
 
-            // If it's precise enough, set it and return
-            if (mid_dist - self.speed).abs() < 0.1 {
-                self.time = mid_time;
-                return mid_point;
-            // Else change the min/max values
-            } else if mid_dist < self.speed {
-                min_time = mid_time;
-            } else {
-                max_time = mid_time;
+                if distance >= waypoint_distance {
+                    distance = waypoint_distance;
+                    self.paused_for = duration;
+                    self.next_pause += 1;
+                }
             }
+
+            self.distance = distance;
         }
-    }
 
+        self.path.point_at(self.distance) + self.offset
+    }
 
     pub fn horizontal(start_y: f32, end_y: f32, left_to_right: bool, speed: f32) -> Self {
         const FORCE: f32 = 1500.0;
         const OFFSET: f32 = 20.0;
 
-        if left_to_right {
-            Self {
-                a: Vector2::new(-FORCE - OFFSET, start_y),
-                b: Vector2::new(-OFFSET, start_y),
-                c: Vector2::new(WIDTH + OFFSET, end_y),
-                d: Vector2::new(WIDTH + FORCE + OFFSET, end_y),
-                time: 0.0,
-                speed,
-            }
+        let points = if left_to_right {
+            [
+                Vector2::new(-FORCE - OFFSET, start_y),
+                Vector2::new(-OFFSET, start_y),
+                Vector2::new(WIDTH + OFFSET, end_y),
+                Vector2::new(WIDTH + FORCE + OFFSET, end_y),
+            ]
         } else {
-            Self {
-                a: Vector2::new(WIDTH + FORCE + OFFSET, start_y),
-                b: Vector2::new(WIDTH + OFFSET, start_y),
-                c: Vector2::new(-OFFSET, end_y),
-                d: Vector2::new(-FORCE - OFFSET, end_y),
-                time: 0.0,
-                speed,
-            }
-        }
+            [
+                Vector2::new(WIDTH + FORCE + OFFSET, start_y),
+                Vector2::new(WIDTH + OFFSET, start_y),
+                Vector2::new(-OFFSET, end_y),
+                Vector2::new(-FORCE - OFFSET, end_y),
+            ]
+        };
+
+        Self::new(SplinePath::catmull_rom(&points), speed)
     }
 
     pub fn vertical(mut start_x: f32, mut end_x: f32, speed: f32) -> Self {
@@ -340,26 +972,195 @@ impl FollowCurve {
         end_x *= WIDTH;
         let force = 2000.0;
 
-        Self {
-            a: Vector2::new(start_x, -20.0 -force),
-            b: Vector2::new(start_x, -20.0),
-            c: Vector2::new(end_x, HEIGHT),
-            d: Vector2::new(end_x, HEIGHT + force),
-            time: 0.0,
-            speed,
-        }
+        Self::new(SplinePath::catmull_rom(&[
+            Vector2::new(start_x, -20.0 -force),
+            Vector2::new(start_x, -20.0),
+            Vector2::new(end_x, HEIGHT),
+            Vector2::new(end_x, HEIGHT + force),
+        ]), speed)
     }
 
     pub fn circular(start_y: f32, force: f32, speed: f32) -> Self {
         let offset = 20.0;
 
-        Self {
-            a: Vector2::new(-offset, start_y - force),
-            b: Vector2::new(-offset, start_y),
-            c: Vector2::new(WIDTH + offset, start_y),
-            d: Vector2::new(WIDTH + offset, start_y - force),
-            time: 0.0,
-            speed
+        Self::new(SplinePath::catmull_rom(&[
+            Vector2::new(-offset, start_y - force),
+            Vector2::new(-offset, start_y),
+            Vector2::new(WIDTH + offset, start_y),
+            Vector2::new(WIDTH + offset, start_y - force),
+        ]), speed)
+    }
+}
+
+// The arrangement of a group of enemies around a shared leader path.
+#[derive(Clone, Copy)]
+pub enum Formation {
+    // A V pointing down the screen, with the leader at the tip.
+    V { spacing: f32 },
+    // A line centered on the leader.
+    Line { spacing: Vector2<f32> },
+    Circle { radius: f32 },
+}
+
+impl Formation {
+    pub fn offsets(self, count: usize) -> Vec<Vector2<f32>> {
+        (0 .. count)
+            .map(|i| match self {
+                Self::V { spacing } => {
+                    let rank = ((i + 1) / 2) as f32;
+                    let side = if i % 2 == 0 { 1.0 } else { -1.0 };
+                    Vector2::new(side * rank * spacing, -rank * spacing)
+                },
+                Self::Line { spacing } => spacing * (i as f32 - (count - 1) as f32 / 2.0),
+                Self::Circle { radius } => {
+                    let rotation = i as f32 / count as f32 * std::f32::consts::PI * 2.0;
+                    Vector2::new(rotation.cos(), rotation.sin()) * radius
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum WaveReward {
+    // Points given to every player.
+    Score(u32),
+    // An item dropped where the last member died.
+    Item(Pickup),
+}
+
+// Tracks a group of enemies so that killing all of them can be rewarded.
+#[derive(Component)]
+pub struct Wave {
+    pub reward: WaveReward,
+    pub size: u32,
+    // Only members killed by players count. Ones that escape or are cleared away don't.
+    pub killed: u32,
+    pub last_position: Vector2<f32>,
+}
+
+impl Wave {
+    pub fn new(reward: WaveReward, size: u32) -> Self {
+        Self { reward, size, killed: 0, last_position: Vector2::new(0.0, 0.0) }
+    }
+}
+
+#[derive(Component)]
+pub struct WaveMember(pub Entity);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boss() -> Boss {
+        let phase = |health_threshold| BossPhase { moves: Vec::new(), health_threshold, duration: Some(10.0), spell_card: None };
+        Boss::new(300, vec![phase(150), phase(0)])
+    }
+
+    #[test]
+    fn timed_out_phase_health_is_clamped() {
+        let mut boss = boss();
+        boss.change_phase(0.0);
+        // The first phase timed out with the boss still on full health
+        assert_eq!(boss.phase_health_perc(300), 1.0);
+        assert_eq!(boss.phase_health_perc(75), 0.5);
+    }
+
+    #[test]
+    fn damage_stops_at_the_phase_threshold() {
+        let mut boss = boss();
+        assert_eq!(boss.clamp_damage(160, 50), 10);
+        assert_eq!(boss.clamp_damage(150, 1), 0);
+
+        boss.change_phase(0.0);
+        assert_eq!(boss.clamp_damage(40, 50), 40);
+    }
+
+    fn random_points(rng: &mut ThreadRng, count: usize) -> Vec<Vector2<f32>> {
+        (0 .. count).map(|_| Vector2::new(rng.gen_range(-500.0, 500.0), rng.gen_range(-500.0, 500.0))).collect()
+    }
+
+    // Random Catmull-Rom and bezier paths, along with where each of their waypoints should be.
+    fn random_paths() -> Vec<(SplinePath, Vec<Vector2<f32>>)> {
+        let mut rng = rand::thread_rng();
+
+        (0 .. 100).map(|i| {
+            let segments = rng.gen_range(1, 5);
+
+            if i % 2 == 0 {
+                let points = random_points(&mut rng, segments + 3);
+                (SplinePath::catmull_rom(&points), points[1 .. points.len() - 1].to_vec())
+            } else {
+                let points = random_points(&mut rng, segments * 3 + 1);
+                (SplinePath::bezier(&points), points.iter().step_by(3).cloned().collect())
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn arc_length_is_monotonic() {
+        for (path, _) in random_paths() {
+            assert!(path.samples.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+
+            // Moving a distance along the path never moves further than that distance
+            let step = 1.7;
+            let mut distance = 0.0;
+
+            while distance < path.length() + 50.0 {
+                let moved = path.point_at(distance).distance(path.point_at(distance + step));
+                assert!(moved <= step + 0.01, "moved {} for a step of {}", moved, step);
+                distance += step;
+            }
         }
     }
+
+    #[test]
+    fn endpoints_are_continuous() {
+        for (path, waypoints) in random_paths() {
+            assert_eq!(path.waypoints.len(), waypoints.len());
+
+            for (distance, expected) in path.waypoints.iter().zip(&waypoints) {
+                assert!(path.point_at(*distance).distance(*expected) < 0.01);
+            }
+
+            // Carrying on past the end doesn't jump
+            let end = path.point_at(path.length());
+            assert!(end.distance(*waypoints.last().unwrap()) < 0.01);
+            assert!(path.point_at(path.length() + 0.5).distance(end) <= 0.5 + 0.01);
+        }
+    }
+
+    #[test]
+    fn following_a_path_ends_once() {
+        for (path, _) in random_paths() {
+            let steps = (path.length() / 2.5) as usize + 2;
+            let mut curve = FollowCurve::new(path, 2.5);
+            let mut ends = 0;
+
+            for _ in 0 .. steps + 10 {
+                curve.step();
+                ends += curve.take_end() as u32;
+            }
+
+            assert!(curve.finished());
+            assert_eq!(ends, 1);
+        }
+    }
+
+    #[test]
+    fn degenerate_paths_dont_panic() {
+        let point = Vector2::new(10.0, 10.0);
+        let path = SplinePath::catmull_rom(&[point; 4]);
+
+        assert_eq!(path.length(), 0.0);
+        assert_eq!(path.point_at(5.0), point);
+        assert_eq!(path.point_at(std::f32::NAN), point);
+    }
+
+    #[test]
+    #[should_panic]
+    fn paths_with_nan_points_are_rejected() {
+        let point = Vector2::new(10.0, 10.0);
+        SplinePath::catmull_rom(&[point, point, Vector2::new(std::f32::NAN, 0.0), point]);
+    }
 }
@@ -0,0 +1,271 @@
+use specs::prelude::*;
+use cgmath::Vector2;
+
+pub mod graphics;
+pub mod components;
+pub mod resources;
+pub mod systems;
+pub mod stages;
+pub mod renderer;
+pub mod archetypes;
+pub mod audio;
+
+use resources::*;
+use stages::StageSetup;
+use renderer::BufferRenderer;
+
+// The game world and the dispatchers that run it, without a window.
+pub struct Game {
+    pub world: World,
+    playing_dispatcher: Dispatcher<'static, 'static>,
+    paused_dispatcher: Dispatcher<'static, 'static>,
+    menu_dispatcher: Dispatcher<'static, 'static>,
+    // Draws the game without updating it, for the ticks skipped by slow motion.
+    render_dispatcher: Dispatcher<'static, 'static>,
+    tick: u64,
+}
+
+impl Game {
+    // The rendering systems are only added if there is something to render to.
+    pub fn new(buffer_renderer: Option<BufferRenderer>, audio: audio::Audio) -> Self {
+        let rendering = buffer_renderer.is_some();
+
+        let mut world = World::new();
+        world.register::<components::Position>();
+        world.register::<components::Image>();
+        world.register::<components::Velocity>();
+        world.register::<components::Falling>();
+        world.register::<components::FollowCurve>();
+        world.register::<components::FiringMove>();
+        world.register::<components::DieOffscreen>();
+        world.register::<components::BackgroundLayer>();
+        world.register::<components::Player>();
+        world.register::<components::FrozenUntil>();
+        world.register::<components::BeenOnscreen>();
+        world.register::<components::FiresBullets>();
+        world.register::<components::Cooldown>();
+        world.register::<components::Friendly>();
+        world.register::<components::Enemy>();
+        world.register::<components::Hitbox>();
+        world.register::<components::Health>();
+        world.register::<components::Explosion>();
+        world.register::<components::Invulnerability>();
+        world.register::<components::Text>();
+        world.register::<components::TargetPlayer>();
+        world.register::<components::Pickup>();
+        world.register::<components::Attracted>();
+        world.register::<components::Spawner>();
+        world.register::<components::SpawnedBy>();
+        world.register::<components::Grazed>();
+        world.register::<components::PowerBar>();
+        world.register::<components::Bomb>();
+        world.register::<components::BombType>();
+        world.register::<components::Score>();
+        world.register::<components::ShotBy>();
+        world.register::<components::PendingHit>();
+        world.register::<components::SpellCardName>();
+        world.register::<components::CollidesWithBomb>();
+        world.register::<components::MoveTowards>();
+        world.register::<components::Boss>();
+        world.register::<components::ColourOverlay>();
+        world.register::<components::Rotation>();
+        world.register::<components::Wave>();
+        world.register::<components::WaveMember>();
+        world.register::<components::DropTable>();
+        world.register::<components::ScoreValue>();
+        world.register::<components::Bot>();
+
+        world.insert(ControlsState::default());
+        world.insert(Settings::default());
+        world.insert(CaptureHistory::default());
+        world.insert(archetypes::Archetypes::load());
+        world.insert(ActiveSpellCard::default());
+        world.insert(audio);
+        world.insert(GameTime::default());
+        world.insert(PlayerPositions::default());
+        world.insert(GameEvents::new());
+        world.insert(Mode::default());
+        world.insert(Difficulty::default());
+        world.insert(Practice::default());
+        world.insert(BossRush::default());
+        world.insert(BossRushRecords::default());
+        world.insert(HighScores::default());
+
+        if let Some(buffer_renderer) = buffer_renderer {
+            world.insert(buffer_renderer);
+        }
+
+        let mut db = DispatcherBuilder::new()
+            .with(systems::FinishStage, "FinishStage", &[])
+            .with(systems::MoveBosses, "MoveBosses", &[])
+            .with(systems::ExplosionImages, "ExplosionImages", &[])
+            .with(systems::TogglePaused, "TogglePaused", &[])
+            .with(systems::KillOffscreen, "KillOffscreen", &[])
+            .with(systems::ExpandBombs, "ExpandBombs", &[])
+            .with(systems::MoveEntities, "MoveEntities", &[])
+            .with(systems::EndPaths::default(), "EndPaths", &["MoveEntities"])
+            .with(systems::AttractItems, "AttractItems", &["MoveEntities"])
+            .with(systems::CollectOrbs, "CollectOrbs", &[])
+            .with(systems::BotControl, "BotControl", &[])
+            .with(systems::Control, "Control", &["BotControl"])
+            .with(systems::SetPlayerPositions, "SetPlayerPositions", &[])
+            .with(systems::FireBullets, "FireBullets", &[])
+            .with(systems::RepeatBackgroundLayers, "RepeatBackgroundLayers", &[])
+            .with(systems::TickTime, "TickTime", &[])
+            .with(systems::StartTowardsPlayer, "StartTowardsPlayer", &["TickTime"])
+            .with(systems::AddOnscreen, "AddOnscreen", &[])
+            .with(systems::Collisions, "Collisions", &[])
+            .with(systems::SpawnChildren, "SpawnChildren", &["Collisions", "ExpandBombs"])
+            .with(systems::CheckWaves::default(), "CheckWaves", &["KillOffscreen", "Collisions", "ExpandBombs"])
+            .with(systems::AwardScore::default(), "AwardScore", &["Collisions", "ExpandBombs", "CollectOrbs", "MoveBosses", "CheckWaves", "FinishStage"])
+            .with(systems::PlaySounds::default(), "PlaySounds", &["Collisions", "ExpandBombs", "CollectOrbs", "Control", "MoveBosses"]);
+
+        if rendering {
+            db.add(systems::ShakeScreen::default(), "ShakeScreen", &["Collisions", "Control"]);
+            db.add(systems::FlashScreen::default(), "FlashScreen", &["Control"]);
+            db.add(systems::RenderSprite::default(), "RenderSprite", &["MoveEntities", "Control", "ExplosionImages", "ShakeScreen"]);
+            db.add(systems::RenderText, "RenderText", &["RenderSprite"]);
+            db.add(systems::RenderBombs, "RenderBombs", &["RenderSprite"]);
+            db.add(systems::RenderHitboxes, "RenderHitboxes", &["RenderSprite"]);
+            db.add(systems::RenderFocusIndicators, "RenderFocusIndicators", &["RenderSprite"]);
+            db.add(systems::RenderUI, "RenderUI", &["RenderSprite"]);
+        }
+
+        log::debug!("{:?}", db);
+
+        let mut playing_dispatcher = db.build();
+
+        let mut paused_db = DispatcherBuilder::new()
+            .with(systems::TogglePaused, "TogglePaused", &[])
+            .with(systems::ControlMenu, "ControlMenu", &[]);
+
+        if rendering {
+            paused_db.add(systems::RenderSprite::default(), "RenderSprite", &[]);
+            paused_db.add(systems::RenderText, "RenderText", &["RenderSprite"]);
+            paused_db.add(systems::RenderBombs, "RenderBombs", &["RenderSprite"]);
+            paused_db.add(systems::RenderHitboxes, "RenderHitboxes", &["RenderSprite"]);
+            paused_db.add(systems::RenderFocusIndicators, "RenderFocusIndicators", &["RenderSprite"]);
+            paused_db.add(systems::RenderUI, "RenderUI", &["RenderSprite"]);
+            paused_db.add(systems::RenderPauseBackground, "RenderPauseBackground", &["RenderSprite"]);
+            paused_db.add(systems::RenderMenu, "RenderMenu", &["RenderSprite"]);
+        }
+
+        let mut paused_dispatcher = paused_db.build();
+
+        let mut menu_db = DispatcherBuilder::new()
+            .with(systems::ControlMenu, "ControlMenu", &[]);
+
+        if rendering {
+            menu_db.add(systems::RenderMenu, "RenderMenu", &[]);
+        }
+
+        let mut menu_dispatcher = menu_db.build();
+
+        let mut render_db = DispatcherBuilder::new();
+
+        if rendering {
+            render_db.add(systems::RenderSprite::default(), "RenderSprite", &[]);
+            render_db.add(systems::RenderText, "RenderText", &["RenderSprite"]);
+            render_db.add(systems::RenderBombs, "RenderBombs", &["RenderSprite"]);
+            render_db.add(systems::RenderHitboxes, "RenderHitboxes", &["RenderSprite"]);
+            render_db.add(systems::RenderFocusIndicators, "RenderFocusIndicators", &["RenderSprite"]);
+            render_db.add(systems::RenderUI, "RenderUI", &["RenderSprite"]);
+        }
+
+        let mut render_dispatcher = render_db.build();
+
+        // Registers the event readers
+        playing_dispatcher.setup(&mut world);
+        paused_dispatcher.setup(&mut world);
+        menu_dispatcher.setup(&mut world);
+        render_dispatcher.setup(&mut world);
+
+        Self { world, playing_dispatcher, paused_dispatcher, menu_dispatcher, render_dispatcher, tick: 0 }
+    }
+
+    // A game with no rendering or sound output, for playing in code.
+    pub fn headless() -> Self {
+        Self::new(None, audio::Audio::default())
+    }
+
+    pub fn mode(&self) -> Mode {
+        *self.world.fetch()
+    }
+
+    // Runs a single tick of whichever mode the game is in.
+    pub fn step(&mut self) {
+        match self.mode() {
+            Mode::MainMenu { .. } | Mode::Stages { .. } | Mode::Practice { .. } | Mode::Controls { .. } | Mode::Settings { .. } | Mode::StageComplete { .. } | Mode::StageLost { .. } | Mode::BossRushComplete { .. } => self.menu_dispatcher.dispatch(&self.world),
+            // Slow motion only updates the game every other tick
+            Mode::Playing { .. } if self.world.fetch::<Practice>().slow_motion && self.tick % 2 == 1 => self.render_dispatcher.dispatch(&self.world),
+            Mode::Playing { .. } => self.playing_dispatcher.dispatch(&self.world),
+            Mode::Paused { .. } => self.paused_dispatcher.dispatch(&self.world),
+            Mode::Quit => {},
+        }
+        self.world.maintain();
+        self.tick += 1;
+    }
+
+    // Skips the menus and goes straight into a stage.
+    pub fn start_stage(&mut self, stage: Stage, setup: StageSetup) {
+        self.world.insert(setup.difficulty);
+        self.world.insert(ActiveSpellCard::default());
+        self.world.fetch_mut::<audio::Audio>().restart_music();
+        self.world.insert(BossRush::default());
+
+        {
+            let entities = self.world.entities();
+            let updater = self.world.fetch::<LazyUpdate>();
+            let archetypes = self.world.fetch::<archetypes::Archetypes>();
+            let mut time = self.world.fetch_mut::<GameTime>();
+
+            stages::start(stage, &entities, &updater, &archetypes, setup, &mut time.total_time);
+        }
+
+        *self.world.fetch_mut::<Mode>() = Mode::Playing {
+            stage, state: PlayingState::Playing, multiplayer: setup.multiplayer, bomb: setup.bomb,
+        };
+        self.world.maintain();
+    }
+
+    // Skips the menus and goes straight into boss rush.
+    pub fn start_boss_rush(&mut self, setup: StageSetup) {
+        self.world.insert(setup.difficulty);
+        self.world.insert(ActiveSpellCard::default());
+        self.world.fetch_mut::<audio::Audio>().restart_music();
+        self.world.insert(BossRush { active: true, boss: 0 });
+
+        {
+            let entities = self.world.entities();
+            let updater = self.world.fetch::<LazyUpdate>();
+            let archetypes = self.world.fetch::<archetypes::Archetypes>();
+            let mut time = self.world.fetch_mut::<GameTime>();
+
+            stages::boss_rush(&entities, &updater, &archetypes, setup, &mut time.total_time);
+        }
+
+        *self.world.fetch_mut::<Mode>() = Mode::Playing {
+            stage: stages::BOSS_RUSH[0], state: PlayingState::Playing, multiplayer: setup.multiplayer, bomb: setup.bomb,
+        };
+        self.world.maintain();
+    }
+
+    // Hands the controls of every player over to the bot.
+    pub fn add_bots(&mut self) {
+        let players: Vec<Entity> = (&self.world.entities(), &self.world.read_storage::<components::Player>()).join()
+            .map(|(entity, _)| entity)
+            .collect();
+
+        let mut bots = self.world.write_storage::<components::Bot>();
+
+        for player in players {
+            bots.insert(player, components::Bot).unwrap();
+        }
+    }
+}
+
+const WIDTH: f32 = 480.0;
+const HEIGHT: f32 = 640.0;
+const DIMENSIONS: Vector2<f32> = Vector2::new(WIDTH, HEIGHT);
+const ZERO: Vector2<f32> = Vector2::new(0.0, 0.0);
+const MIDDLE: Vector2<f32> = Vector2::new(WIDTH / 2.0, HEIGHT / 2.0);
@@ -4,11 +4,14 @@ use winit::{
 };
 
 use cgmath::*;
-use crate::{WIDTH, HEIGHT};
+use crate::{WIDTH, HEIGHT, DIMENSIONS, MIDDLE};
 use crate::components::{Image, Text};
+use crate::resources::{Settings, WindowMode, Scaling, PostProcessing};
 use zerocopy::*;
 use wgpu::util::DeviceExt;
 
+pub mod software;
+
 pub struct Renderer {
     swap_chain: wgpu::SwapChain,
     device: wgpu::Device,
@@ -22,19 +25,181 @@ pub struct Renderer {
     square_buffer: wgpu::Buffer,
     uniform_buffer: wgpu::Buffer,
     instance_buffer: GpuBuffer<'static, Instance>,
+    window_mode: WindowMode,
+    bind_group_layout: wgpu::BindGroupLayout,
+    linear_sampler: wgpu::Sampler,
+    blit_instance_buffer: wgpu::Buffer,
+    // The playfield at its native resolution, for the scaling modes that don't draw straight to the screen.
+    offscreen: wgpu::TextureView,
+    offscreen_blit: Blit,
+    // The offscreen texture upscaled by a whole number, for sharp-bilinear filtering.
+    prescaled: Option<Prescaled>,
+    additive_pipeline: wgpu::RenderPipeline,
+    multiply_pipeline: wgpu::RenderPipeline,
+    // Glowing sprites are drawn to this at a quarter of the resolution, and then blurred by upscaling.
+    glow: wgpu::TextureView,
+    glow_bind_group: wgpu::BindGroup,
+    glow_uniform_buffer: wgpu::Buffer,
+    glow_blit: Blit,
+    glow_instance_buffer: GpuBuffer<'static, Instance>,
+    effect_instance_buffer: GpuBuffer<'static, Instance>,
+}
+
+const GLOW_SIZE: Vector2<f32> = Vector2::new(WIDTH / 4.0, HEIGHT / 4.0);
+const BLOOM_STRENGTH: f64 = 0.8;
+// Multiplied with the playfield when colour grading is on.
+const COLOUR_GRADE: [f32; 4] = [1.0, 0.88, 0.92, 1.0];
+const SCANLINE_BRIGHTNESS: f32 = 0.7;
+
+fn fonts() -> Vec<wgpu_glyph::ab_glyph::FontRef<'static>> {
+    vec![
+        wgpu_glyph::ab_glyph::FontRef::try_from_slice(include_bytes!("fonts/OldeEnglish.ttf")).unwrap(),
+        wgpu_glyph::ab_glyph::FontRef::try_from_slice(include_bytes!("fonts/TinyUnicode.ttf")).unwrap()
+    ]
+}
+
+const BORDER_COLOUR: wgpu::Color = wgpu::Color { r: 0.5, g: 0.125, b: 0.125, a: 1.0 };
+
+// Draws a texture onto the whole playfield.
+const BLIT_INSTANCE: Instance = Instance {
+    center: [MIDDLE.x, MIDDLE.y],
+    dimensions: [WIDTH, HEIGHT],
+    rotation: 0.0,
+    uv_top_left: [0.0; 2],
+    uv_dimensions: [1.0; 2],
+    overlay: [0.0; 4],
+    overlay_only: 0,
+};
+
+struct Prescaled {
+    factor: u32,
+    texture: wgpu::TextureView,
+    blit: Blit,
 }
 
+// A texture and the uniforms used to draw it.
+struct Blit {
+    bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+}
+
+impl Blit {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, texture: &wgpu::TextureView, sampler: &wgpu::Sampler) -> Self {
+        let uniform_buffer = create_uniform_buffer(device);
+        let bind_group = create_bind_group(device, layout, texture, sampler, &uniform_buffer);
+
+        Self { bind_group, uniform_buffer }
+    }
+}
+
+fn create_uniform_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Hectic uniform buffer"),
+        contents: Uniforms::new(DIMENSIONS).as_bytes(),
+        usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+    })
+}
+
+fn create_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, texture: &wgpu::TextureView, sampler: &wgpu::Sampler, uniform_buffer: &wgpu::Buffer) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(texture),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Buffer(
+                    uniform_buffer.slice(0 .. std::mem::size_of::<Uniforms>() as u64)
+                )
+            }
+        ],
+        label: Some("Hectic BindGroup"),
+    })
+}
+
+const ALPHA_BLEND: wgpu::BlendDescriptor = wgpu::BlendDescriptor {
+    src_factor: wgpu::BlendFactor::SrcAlpha,
+    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+    operation: wgpu::BlendOperation::Add,
+};
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, vs_module: &wgpu::ShaderModule, fs_module: &wgpu::ShaderModule, color_blend: wgpu::BlendDescriptor) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Hectic RenderPipeline"),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        color_states: &[wgpu::ColorStateDescriptor {
+            format: wgpu::TextureFormat::Bgra8Unorm,
+            color_blend,
+            alpha_blend: wgpu::BlendDescriptor {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::DstAlpha,
+                operation: wgpu::BlendOperation::Max,
+            },
+            write_mask: wgpu::ColorWrite::ALL,
+        }],
+        depth_stencil_state: None,
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint16,
+            vertex_buffers: &[
+                wgpu::VertexBufferDescriptor {
+                    stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::InputStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float2],
+                },
+                wgpu::VertexBufferDescriptor {
+                    stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
+                    step_mode: wgpu::InputStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![1 => Float2, 2 => Float2, 3 => Float, 4 => Float2, 5 => Float2, 6 => Float4, 7 => Int]
+                }
+            ],
+        },
+        sample_count: 1,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
+
+fn create_render_target(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d { width, height, depth: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Bgra8Unorm,
+        usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        label: Some("Hectic render target"),
+    });
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+#[cfg(feature = "wasm")]
+const WASM_WINDOWED_SIZE: winit::dpi::LogicalSize<f64> = winit::dpi::LogicalSize::new(1270.0, 720.0);
+
 impl Renderer {
     pub async fn new(event_loop: &EventLoop<()>) -> (Self, BufferRenderer) {
         let window = Window::new(event_loop).unwrap();
 
-        //#[cfg(feature = "native")]
-        //window.set_fullscreen(Some(Fullscreen::Borderless(event_loop.primary_monitor())));
-
         #[cfg(feature = "wasm")]
         {
-            // Going fullscreen seems to crash on the web, so we just use a fixed window size for now.
-            window.set_inner_size(winit::dpi::LogicalSize::new(1270.0, 720.0));
+            // The canvas doesn't have a size of its own outside of fullscreen.
+            window.set_inner_size(WASM_WINDOWED_SIZE);
 
             use winit::platform::web::WindowExtWebSys;
             web_sys::window()
@@ -71,12 +236,7 @@ impl Renderer {
         let fs = wgpu::include_spirv!("shader.frag.spv");
         let fs_module = device.create_shader_module(fs);
     
-        let fonts = vec![
-            wgpu_glyph::ab_glyph::FontRef::try_from_slice(include_bytes!("fonts/OldeEnglish.ttf")).unwrap(),
-            wgpu_glyph::ab_glyph::FontRef::try_from_slice(include_bytes!("fonts/TinyUnicode.ttf")).unwrap()
-        ];
-
-        let glyph_brush = wgpu_glyph::GlyphBrushBuilder::using_fonts(fonts)
+        let glyph_brush = wgpu_glyph::GlyphBrushBuilder::using_fonts(fonts())
             .texture_filter_method(wgpu::FilterMode::Nearest)
             .build(&device, wgpu::TextureFormat::Bgra8Unorm);
 
@@ -120,31 +280,28 @@ impl Renderer {
 
         let window_size = window.inner_size();
 
-        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Hectic uniform buffer"),
-            contents: Uniforms::new(window_size.width, window_size.height).as_bytes(),
-            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        let uniform_buffer = create_uniform_buffer(&device);
+        let bind_group = create_bind_group(&device, &bind_group_layout, &texture, &sampler, &uniform_buffer);
+
+        let linear_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            label: Some("Hectic linear Sampler"),
+            ..Default::default()
         });
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Buffer(
-                        uniform_buffer.slice(0 .. std::mem::size_of::<Uniforms>() as u64)
-                    )
-                }
-            ],
-            label: Some("Hectic BindGroup"),
+        let offscreen = create_render_target(&device, WIDTH as u32, HEIGHT as u32);
+        let offscreen_blit = Blit::new(&device, &bind_group_layout, &offscreen, &sampler);
+
+        let glow = create_render_target(&device, GLOW_SIZE.x as u32, GLOW_SIZE.y as u32);
+        let glow_uniform_buffer = create_uniform_buffer(&device);
+        let glow_bind_group = create_bind_group(&device, &bind_group_layout, &texture, &sampler, &glow_uniform_buffer);
+        let glow_blit = Blit::new(&device, &bind_group_layout, &glow, &linear_sampler);
+
+        let blit_instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Hectic blit instance buffer"),
+            contents: [BLIT_INSTANCE].as_bytes(),
+            usage: wgpu::BufferUsage::VERTEX,
         });
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -153,52 +310,17 @@ impl Renderer {
             push_constant_ranges: &[]
         });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Hectic RenderPipeline"),
-            layout: Some(&pipeline_layout),
-            vertex_stage: wgpu::ProgrammableStageDescriptor {
-                module: &vs_module,
-                entry_point: "main",
-            },
-            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-                module: &fs_module,
-                entry_point: "main",
-            }),
-            rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
-            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
-            color_states: &[wgpu::ColorStateDescriptor {
-                format: wgpu::TextureFormat::Bgra8Unorm,
-                color_blend: wgpu::BlendDescriptor {
-                    src_factor: wgpu::BlendFactor::SrcAlpha,
-                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                    operation: wgpu::BlendOperation::Add,
-                },
-                alpha_blend: wgpu::BlendDescriptor {
-                    src_factor: wgpu::BlendFactor::SrcAlpha,
-                    dst_factor: wgpu::BlendFactor::DstAlpha,
-                    operation: wgpu::BlendOperation::Max,
-                },
-                write_mask: wgpu::ColorWrite::ALL,
-            }],
-            depth_stencil_state: None,
-            vertex_state: wgpu::VertexStateDescriptor {
-                index_format: wgpu::IndexFormat::Uint16,
-                vertex_buffers: &[
-                    wgpu::VertexBufferDescriptor {
-                        stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-                        step_mode: wgpu::InputStepMode::Vertex,
-                        attributes: &wgpu::vertex_attr_array![0 => Float2],
-                    },
-                    wgpu::VertexBufferDescriptor {
-                        stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
-                        step_mode: wgpu::InputStepMode::Instance,
-                        attributes: &wgpu::vertex_attr_array![1 => Float2, 2 => Float2, 3 => Float, 4 => Float2, 5 => Float2, 6 => Float4, 7 => Int]
-                    }
-                ],
-            },
-            sample_count: 1,
-            sample_mask: !0,
-            alpha_to_coverage_enabled: false,
+        let pipeline = create_pipeline(&device, &pipeline_layout, &vs_module, &fs_module, ALPHA_BLEND);
+        // Adds the source multiplied by the blend colour.
+        let additive_pipeline = create_pipeline(&device, &pipeline_layout, &vs_module, &fs_module, wgpu::BlendDescriptor {
+            src_factor: wgpu::BlendFactor::BlendColor,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        });
+        let multiply_pipeline = create_pipeline(&device, &pipeline_layout, &vs_module, &fs_module, wgpu::BlendDescriptor {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::SrcColor,
+            operation: wgpu::BlendOperation::Add,
         });
     
         let swap_chain_desc = wgpu::SwapChainDescriptor {
@@ -213,13 +335,11 @@ impl Renderer {
 
         queue.submit(Some(init_encoder.finish()));
 
-        let buffer_renderer = BufferRenderer {
-            glyph_sections: Vec::new(),
-            instances: Vec::new(),
-            window_size: Vector2::new(window_size.width as f32, window_size.height as f32),
-        };
+        let buffer_renderer = BufferRenderer::new(window_size.width, window_size.height);
 
         let instance_buffer = GpuBuffer::new(&device, 2560, "Hectic instances buffer");
+        let glow_instance_buffer = GpuBuffer::new(&device, 640, "Hectic glow instances buffer");
+        let effect_instance_buffer = GpuBuffer::new(&device, 400, "Hectic effect instances buffer");
 
         let square_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Hectic square buffer"),
@@ -230,6 +350,11 @@ impl Renderer {
         let renderer = Self {
             square_buffer, swap_chain, pipeline, window, device, queue, swap_chain_desc, surface,
             bind_group, uniform_buffer, instance_buffer, glyph_brush,
+            window_mode: WindowMode::Windowed,
+            bind_group_layout, linear_sampler, blit_instance_buffer, offscreen, offscreen_blit,
+            prescaled: None,
+            additive_pipeline, multiply_pipeline, glow, glow_bind_group, glow_uniform_buffer, glow_blit,
+            glow_instance_buffer, effect_instance_buffer,
         };
 
         (renderer, buffer_renderer)
@@ -239,36 +364,137 @@ impl Renderer {
         self.swap_chain_desc.width = width;
         self.swap_chain_desc.height = height;
         self.swap_chain = self.device.create_swap_chain(&self.surface, &self.swap_chain_desc);
+    }
 
-        let staging_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Hectic uniform staging buffer"),
-            contents: Uniforms::new(width, height).as_bytes(),
-            usage: wgpu::BufferUsage::COPY_SRC,
-        });
+    // Only does anything when a setting has changed.
+    pub fn apply_settings(&mut self, settings: &Settings, renderer: &mut BufferRenderer) {
+        if settings.window_mode != self.window_mode {
+            self.window_mode = settings.window_mode;
+            self.set_window_mode();
+        }
+
+        #[cfg(feature = "wasm")]
+        self.fit_canvas(renderer);
+
+        let present_mode = if settings.vsync { wgpu::PresentMode::Fifo } else { wgpu::PresentMode::Immediate };
 
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Hectic CommandEncoder") });
-        encoder.copy_buffer_to_buffer(&staging_buffer, 0, &self.uniform_buffer, 0, std::mem::size_of::<Uniforms>() as u64);
-        self.queue.submit(Some(encoder.finish()));
+        if present_mode != self.swap_chain_desc.present_mode {
+            self.swap_chain_desc.present_mode = present_mode;
+            self.swap_chain = self.device.create_swap_chain(&self.surface, &self.swap_chain_desc);
+        }
+
+        renderer.scaling = settings.scaling;
+        renderer.post_processing = settings.post_processing;
+    }
+
+    // Changing the window mode on native sends a resize event, which is where the swap chain gets resized.
+    #[cfg(feature = "native")]
+    fn set_window_mode(&mut self) {
+        use winit::window::Fullscreen;
+
+        let monitor = self.window.current_monitor();
+
+        let fullscreen = match self.window_mode {
+            WindowMode::Windowed => None,
+            WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+            WindowMode::Exclusive => {
+                // Use the biggest, then fastest, video mode the monitor has.
+                let video_mode = monitor.as_ref().and_then(|monitor| {
+                    monitor.video_modes().max_by_key(|mode| (mode.size().width * mode.size().height, mode.refresh_rate()))
+                });
+
+                match video_mode {
+                    Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+                    None => {
+                        log::warn!("No video modes available for exclusive fullscreen, using borderless instead.");
+                        Some(Fullscreen::Borderless(monitor))
+                    }
+                }
+            }
+        };
+
+        self.window.set_fullscreen(fullscreen);
+    }
+
+    // Browsers only have the one kind of fullscreen.
+    #[cfg(feature = "wasm")]
+    fn set_window_mode(&mut self) {
+        use winit::platform::web::WindowExtWebSys;
+
+        let document = match web_sys::window().and_then(|win| win.document()) {
+            Some(document) => document,
+            None => return,
+        };
+
+        let result = match self.window_mode {
+            WindowMode::Windowed => {
+                document.exit_fullscreen();
+                Ok(())
+            },
+            WindowMode::Borderless | WindowMode::Exclusive => self.window.canvas().request_fullscreen(),
+        };
+
+        if let Err(error) = result {
+            log::warn!("Fullscreen request failed: {:?}", error);
+        }
+    }
+
+    // Going in and out of browser fullscreen happens asynchronously (and the user can leave it with escape),
+    // so the canvas is resized to match whenever it changes.
+    #[cfg(feature = "wasm")]
+    fn fit_canvas(&mut self, renderer: &mut BufferRenderer) {
+        let win = match web_sys::window() {
+            Some(win) => win,
+            None => return,
+        };
+
+        let fullscreen = win.document().and_then(|doc| doc.fullscreen_element()).is_some();
+
+        let size = if fullscreen {
+            let width = win.inner_width().ok().and_then(|width| width.as_f64());
+            let height = win.inner_height().ok().and_then(|height| height.as_f64());
+
+            match (width, height) {
+                (Some(width), Some(height)) => winit::dpi::LogicalSize::new(width, height),
+                _ => return,
+            }
+        } else {
+            WASM_WINDOWED_SIZE
+        };
+
+        let physical_size = size.to_physical::<u32>(self.window.scale_factor());
+
+        if physical_size != self.window.inner_size() {
+            self.window.set_inner_size(size);
+            self.resize(physical_size.width, physical_size.height);
+            renderer.set_window_size(physical_size.width, physical_size.height);
+        }
     }
 
     pub fn render(&mut self, renderer: &mut BufferRenderer) {
         let offset = renderer.centering_offset() / 2.0;
         let dimensions = renderer.dimensions();
+        let target_size = renderer.target_size();
+        let offscreen = renderer.offscreen();
 
         if let Ok(frame) = self.swap_chain.get_current_frame() {
             let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Hectic CommandEncoder") });
 
+            renderer.render_flash();
             renderer.render_borders();
 
             self.instance_buffer.upload(&self.device, &mut encoder, &renderer.instances);
+            self.queue.write_buffer(&self.uniform_buffer, 0, Uniforms::new(target_size).as_bytes());
+
+            let target = if offscreen { &self.offscreen } else { &frame.output.view };
 
             {
                 let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                        attachment: &frame.output.view,
+                        attachment: target,
                         resolve_target: None,
                         ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color { r: 0.5, g: 0.125, b: 0.125, a: 1.0 }),
+                            load: wgpu::LoadOp::Clear(BORDER_COLOUR),
                             store: true,
                         },
                     }],
@@ -298,26 +524,168 @@ impl Renderer {
                 &self.device,
                 &mut staging_belt,
                 &mut encoder,
-                &frame.output.view,
-                wgpu_glyph::orthographic_projection(renderer.window_size.x as u32, renderer.window_size.y as u32),
-                wgpu_glyph::Region { x: 0, y: offset.y as u32, width: renderer.window_size.x as u32, height: dimensions.y as u32 },
+                target,
+                wgpu_glyph::orthographic_projection(target_size.x as u32, target_size.y as u32),
+                wgpu_glyph::Region { x: 0, y: offset.y as u32, width: target_size.x as u32, height: dimensions.y as u32 },
             ).unwrap();
             #[cfg(feature = "wasm")]
             self.glyph_brush.draw_queued(
                 &self.device,
                 &mut staging_belt,
                 &mut encoder,
-                &frame.output.view,
-                self.swap_chain_desc.width,
-                self.swap_chain_desc.height,
+                target,
+                target_size.x as u32,
+                target_size.y as u32,
             ).unwrap();
 
             staging_belt.finish();
 
-            self.queue.submit(Some(encoder.finish()));    
+            if offscreen {
+                self.post_process(&mut encoder, renderer);
+                self.output(&mut encoder, &frame.output.view, renderer);
+            }
+
+            self.queue.submit(Some(encoder.finish()));
         }
 
-        renderer.instances.clear();
+        renderer.clear();
+    }
+
+    // Draws the post-processing effects that are turned on over the offscreen texture.
+    fn post_process(&mut self, encoder: &mut wgpu::CommandEncoder, renderer: &BufferRenderer) {
+        let post_processing = renderer.post_processing;
+
+        if !post_processing.any() {
+            return;
+        }
+
+        if post_processing.bloom {
+            self.glow_instance_buffer.upload(&self.device, encoder, &renderer.glow_instances);
+            self.queue.write_buffer(&self.glow_uniform_buffer, 0, Uniforms::new(GLOW_SIZE).as_bytes());
+            self.queue.write_buffer(&self.glow_blit.uniform_buffer, 0, Uniforms::new(DIMENSIONS).as_bytes());
+
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: &self.glow,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            if self.glow_instance_buffer.len > 0 {
+                rpass.set_pipeline(&self.pipeline);
+                rpass.set_bind_group(0, &self.glow_bind_group, &[]);
+                rpass.set_vertex_buffer(0, self.square_buffer.slice(..));
+                let byte_len = self.glow_instance_buffer.byte_len() as u64;
+                rpass.set_vertex_buffer(1, self.glow_instance_buffer.buffer.slice(..byte_len));
+                rpass.draw(0 .. SQUARE.len() as u32, 0 .. self.glow_instance_buffer.len as u32);
+            }
+        }
+
+        let mut effects = Vec::new();
+
+        if post_processing.colour_grading {
+            effects.push(Instance::overlay(MIDDLE, DIMENSIONS, 0.0, COLOUR_GRADE));
+        }
+
+        if post_processing.scanlines {
+            let colour = [SCANLINE_BRIGHTNESS, SCANLINE_BRIGHTNESS, SCANLINE_BRIGHTNESS, 1.0];
+            effects.extend((1 .. HEIGHT as u32).step_by(2).map(|y| {
+                Instance::overlay(Vector2::new(MIDDLE.x, y as f32 + 0.5), Vector2::new(WIDTH, 1.0), 0.0, colour)
+            }));
+        }
+
+        self.effect_instance_buffer.upload(&self.device, encoder, &effects);
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: &self.offscreen,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        rpass.set_vertex_buffer(0, self.square_buffer.slice(..));
+
+        if post_processing.bloom {
+            rpass.set_pipeline(&self.additive_pipeline);
+            rpass.set_blend_color(wgpu::Color { r: BLOOM_STRENGTH, g: BLOOM_STRENGTH, b: BLOOM_STRENGTH, a: 1.0 });
+            rpass.set_bind_group(0, &self.glow_blit.bind_group, &[]);
+            rpass.set_vertex_buffer(1, self.blit_instance_buffer.slice(..));
+            rpass.draw(0 .. SQUARE.len() as u32, 0 .. 1);
+        }
+
+        let len = self.effect_instance_buffer.len as u32;
+
+        if len > 0 {
+            let byte_len = self.effect_instance_buffer.byte_len() as u64;
+            rpass.set_vertex_buffer(1, self.effect_instance_buffer.buffer.slice(..byte_len));
+            rpass.set_bind_group(0, &self.bind_group, &[]);
+            rpass.set_pipeline(&self.multiply_pipeline);
+            rpass.draw(0 .. SQUARE.len() as u32, 0 .. len);
+        }
+    }
+
+    // Scales the offscreen texture onto the frame.
+    fn output(&mut self, encoder: &mut wgpu::CommandEncoder, frame: &wgpu::TextureView, renderer: &BufferRenderer) {
+        let output_offset = (renderer.window_size - renderer.output_dimensions()) / 2.0;
+        let output_dimensions = renderer.output_dimensions();
+
+        match renderer.scaling {
+            Scaling::Fractional | Scaling::Integer => {
+                self.blit(encoder, frame, &self.offscreen_blit, output_offset, output_dimensions);
+            },
+            Scaling::SharpBilinear => {
+                // Upscale by the next whole number with nearest filtering, then scale down the
+                // rest of the way with linear filtering, so that pixels stay sharp but even.
+                let factor = renderer.output_scale_factor().ceil().max(1.0) as u32;
+                let prescaled_size = DIMENSIONS * factor as f32;
+
+                if self.prescaled.as_ref().map(|prescaled| prescaled.factor) != Some(factor) {
+                    let texture = create_render_target(&self.device, prescaled_size.x as u32, prescaled_size.y as u32);
+                    let blit = Blit::new(&self.device, &self.bind_group_layout, &texture, &self.linear_sampler);
+                    self.prescaled = Some(Prescaled { factor, texture, blit });
+                }
+
+                if let Some(prescaled) = &self.prescaled {
+                    self.blit(encoder, &prescaled.texture, &self.offscreen_blit, Vector2::zero(), prescaled_size);
+                    self.blit(encoder, frame, &prescaled.blit, output_offset, output_dimensions);
+                }
+            },
+        }
+    }
+
+    // Draws a texture over the area of `target` given by `offset` and `dimensions`, filling the rest with the border colour.
+    fn blit(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView, blit: &Blit, offset: Vector2<f32>, dimensions: Vector2<f32>) {
+        self.queue.write_buffer(&blit.uniform_buffer, 0, Uniforms::new(dimensions).as_bytes());
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(BORDER_COLOUR),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        // The shader scales the playfield to fill the viewport, which letterboxes it.
+        rpass.set_viewport(offset.x, offset.y, dimensions.x, dimensions.y, 0.0, 1.0);
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &blit.bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.square_buffer.slice(..));
+        rpass.set_vertex_buffer(1, self.blit_instance_buffer.slice(..));
+        rpass.draw(0 .. SQUARE.len() as u32, 0 .. 1);
     }
 
     pub fn request_redraw(&mut self) {
@@ -412,6 +780,20 @@ pub struct Instance {
     overlay_only: i32
 }
 
+impl Instance {
+    fn overlay(center: Vector2<f32>, dimensions: Vector2<f32>, rotation: f32, overlay: [f32; 4]) -> Self {
+        Self {
+            center: center.into(),
+            dimensions: dimensions.into(),
+            rotation,
+            uv_top_left: [0.0; 2],
+            uv_dimensions: [0.0; 2],
+            overlay,
+            overlay_only: true as i32
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(zerocopy::AsBytes, Clone, Debug)]
 pub struct Uniforms {
@@ -420,9 +802,9 @@ pub struct Uniforms {
 }
 
 impl Uniforms {
-    fn new(width: u32, height: u32) -> Self {
+    fn new(window_size: Vector2<f32>) -> Self {
         Self {
-            window_size: [width as f32, height as f32],
+            window_size: window_size.into(),
             virtual_size: [WIDTH, HEIGHT]
         }
     }
@@ -435,6 +817,14 @@ pub struct BufferRenderer {
     // todo: maybe store something lighter here so we can use cow strs
     glyph_sections: Vec<glyph_brush::OwnedSection<wgpu_glyph::Extra>>,
     instances: Vec<Instance>,
+    scaling: Scaling,
+    post_processing: PostProcessing,
+    // Sprites that are drawn again for the bloom effect.
+    glow_instances: Vec<Instance>,
+    // Added to the position of everything except text.
+    shake: Vector2<f32>,
+    // The alpha of a white overlay over everything.
+    flash: f32,
 }
 
 impl Default for BufferRenderer {
@@ -444,17 +834,81 @@ impl Default for BufferRenderer {
 }
 
 impl BufferRenderer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            glyph_sections: Vec::new(),
+            instances: Vec::new(),
+            window_size: Vector2::new(width as f32, height as f32),
+            scaling: Scaling::Fractional,
+            post_processing: PostProcessing::default(),
+            glow_instances: Vec::new(),
+            flash: 0.0,
+            shake: Vector2::zero(),
+        }
+    }
+
     pub fn set_window_size(&mut self, width: u32, height: u32) {
         self.window_size = Vector2::new(width as f32, height as f32);
     }
 
+    // Throws away everything that has been drawn since the last frame.
+    pub fn clear(&mut self) {
+        self.instances.clear();
+        self.glow_instances.clear();
+        self.glyph_sections.clear();
+        // These are set again each frame while playing
+        self.shake = Vector2::zero();
+        self.flash = 0.0;
+    }
+
+    pub fn set_shake(&mut self, shake: Vector2<f32>) {
+        self.shake = shake;
+    }
+
+    pub fn set_flash(&mut self, flash: f32) {
+        self.flash = flash;
+    }
+
+    // Post-processing needs the playfield in a texture, as does scaling it by anything other than a fraction.
+    fn offscreen(&self) -> bool {
+        self.scaling != Scaling::Fractional || self.post_processing.any()
+    }
+
+    // The size of what the game is drawn onto.
+    fn target_size(&self) -> Vector2<f32> {
+        if self.offscreen() {
+            DIMENSIONS
+        } else {
+            self.window_size
+        }
+    }
+
     pub fn scale_factor(&self) -> f32 {
-        (self.window_size.y / crate::HEIGHT)
-            .min(self.window_size.x / crate::WIDTH)
+        let target_size = self.target_size();
+
+        (target_size.y / crate::HEIGHT)
+            .min(target_size.x / crate::WIDTH)
+    }
+
+    // How much the offscreen texture is scaled by to fit the window.
+    fn output_scale_factor(&self) -> f32 {
+        let scale_factor = (self.window_size.y / crate::HEIGHT)
+            .min(self.window_size.x / crate::WIDTH);
+
+        // Windows smaller than the game can't be scaled down by a whole number
+        if self.scaling == Scaling::Integer && scale_factor >= 1.0 {
+            scale_factor.floor()
+        } else {
+            scale_factor
+        }
+    }
+
+    fn output_dimensions(&self) -> Vector2<f32> {
+        DIMENSIONS * self.output_scale_factor()
     }
 
     fn centering_offset(&self) -> Vector2<f32> {
-        self.window_size - self.dimensions()
+        self.target_size() - self.dimensions()
     }
 
     fn dimensions(&self) -> Vector2<f32> {
@@ -469,7 +923,7 @@ impl BufferRenderer {
         let (uv_x, uv_y, uv_w, uv_h) = sprite.coordinates();
 
         self.instances.push(Instance {
-            center: center.into(),
+            center: (center + self.shake).into(),
             dimensions: dimensions.into(),
             rotation,
             uv_top_left: [uv_x, uv_y].into(),
@@ -479,16 +933,23 @@ impl BufferRenderer {
         });
     }
 
+    // Like `render_sprite`, but also adds the sprite to the bloom.
+    pub fn render_glowing_sprite(&mut self, sprite: Image, pos: Vector2<f32>, rotation: f32, overlay: [f32; 4]) {
+        self.render_sprite(sprite, pos, rotation, overlay);
+
+        if self.post_processing.bloom {
+            if let Some(instance) = self.instances.last() {
+                self.glow_instances.push(instance.clone());
+            }
+        }
+    }
+
     pub fn render_box(&mut self, center: Vector2<f32>, dimensions: Vector2<f32>, overlay: [f32; 4]) {
-        self.instances.push(Instance {
-            center: center.into(),
-            dimensions: dimensions.into(),
-            rotation: 0.0,
-            uv_top_left: [0.0; 2],
-            uv_dimensions: [0.0; 2],
-            overlay,
-            overlay_only: true as i32
-        });
+        self.render_rotated_box(center, dimensions, 0.0, overlay);
+    }
+
+    pub fn render_rotated_box(&mut self, center: Vector2<f32>, dimensions: Vector2<f32>, rotation: f32, overlay: [f32; 4]) {
+        self.instances.push(Instance::overlay(center + self.shake, dimensions, rotation, overlay));
     }
 
     pub fn render_text(&mut self, text: &Text, mut pos: Vector2<f32>, color: [f32; 4]) {
@@ -532,19 +993,49 @@ impl BufferRenderer {
         }
     }
 
+    // The flash is a normal overlay, so that it doesn't need the playfield to be drawn offscreen.
+    pub fn render_flash(&mut self) {
+        if let Some(flash) = self.flash_instance() {
+            self.instances.push(flash);
+        }
+    }
+
+    fn flash_instance(&self) -> Option<Instance> {
+        if self.post_processing.bomb_flash && self.flash > 0.0 {
+            Some(Instance::overlay(MIDDLE, DIMENSIONS, 0.0, [1.0, 1.0, 1.0, self.flash]))
+        } else {
+            None
+        }
+    }
+
     pub fn render_borders(&mut self) {
+        let borders = self.border_instances();
+        self.instances.extend(borders);
+    }
+
+    fn border_instances(&self) -> Vec<Instance> {
+        let mut borders = Vec::new();
+
+        // Offscreen targets are the size of the playfield, and get their borders from the clear colour in `blit`
+        if self.offscreen() {
+            return borders;
+        }
+
         let colour = [0.5, 0.125, 0.125, 1.0];
+
         let border_width = ((self.window_size.x / self.scale_factor()) - WIDTH) / 2.0;
         if border_width > 0.0 {
-            self.render_box(Vector2::new(-border_width / 2.0, 0.0),        Vector2::new(border_width, HEIGHT * 2.0), colour);
-            self.render_box(Vector2::new(WIDTH + border_width / 2.0, 0.0), Vector2::new(border_width, HEIGHT * 2.0), colour);
+            borders.push(Instance::overlay(Vector2::new(-border_width / 2.0, 0.0),        Vector2::new(border_width, HEIGHT * 2.0), 0.0, colour));
+            borders.push(Instance::overlay(Vector2::new(WIDTH + border_width / 2.0, 0.0), Vector2::new(border_width, HEIGHT * 2.0), 0.0, colour));
         }
 
         let border_height = ((self.window_size.y / self.scale_factor()) - HEIGHT) / 2.0;
 
         if border_height > 0.0 {
-            self.render_box(Vector2::new(0.0, -border_height / 2.0), Vector2::new(WIDTH * 2.0, border_height), colour);
-            self.render_box(Vector2::new(0.0, HEIGHT + border_height / 2.0), Vector2::new(WIDTH * 2.0, border_height), colour);
+            borders.push(Instance::overlay(Vector2::new(0.0, -border_height / 2.0), Vector2::new(WIDTH * 2.0, border_height), 0.0, colour));
+            borders.push(Instance::overlay(Vector2::new(0.0, HEIGHT + border_height / 2.0), Vector2::new(WIDTH * 2.0, border_height), 0.0, colour));
         }
+
+        borders
     }
 }
@@ -0,0 +1,187 @@
+// A CPU version of the wgpu renderer, for checking what is drawn without a GPU.
+// It draws the same instances and glyph sections, but skips the post-processing and scaling passes.
+
+use cgmath::Vector2;
+use glyph_brush::GlyphPositioner;
+use wgpu_glyph::ab_glyph::{Font, FontRef};
+use super::{BufferRenderer, Instance};
+
+pub struct SoftwareRenderer {
+    texture: image::RgbaImage,
+    fonts: Vec<FontRef<'static>>,
+}
+
+impl Default for SoftwareRenderer {
+    fn default() -> Self {
+        let texture = image::load_from_memory_with_format(
+            include_bytes!(concat!(env!("OUT_DIR"), "/packed.png")), image::ImageFormat::Png
+        ).unwrap().into_rgba();
+
+        Self { texture, fonts: super::fonts() }
+    }
+}
+
+impl SoftwareRenderer {
+    // Draws the current frame. Unlike the wgpu renderer, this doesn't clear the buffer renderer.
+    pub fn render(&self, renderer: &BufferRenderer) -> image::RgbaImage {
+        let size = renderer.target_size();
+        let mut canvas = Canvas::new(size.x as u32, size.y as u32);
+
+        let scale_factor = renderer.scale_factor();
+        let offset = renderer.centering_offset() / 2.0;
+
+        for instance in renderer.instances.iter().chain(&renderer.flash_instance()).chain(&renderer.border_instances()) {
+            self.draw_instance(&mut canvas, instance, scale_factor, offset);
+        }
+
+        // Text is clipped vertically to the playfield like the native glyph renderer.
+        let clip = (offset.y, offset.y + renderer.dimensions().y);
+
+        for section in &renderer.glyph_sections {
+            self.draw_section(&mut canvas, section, clip);
+        }
+
+        canvas.into_image()
+    }
+
+    // Does what the vertex and fragment shaders do, for each pixel the instance could cover.
+    fn draw_instance(&self, canvas: &mut Canvas, instance: &Instance, scale_factor: f32, offset: Vector2<f32>) {
+        let center = Vector2::from(instance.center) * scale_factor + offset;
+        let half_size = Vector2::from(instance.dimensions) * scale_factor / 2.0;
+        let (sin, cos) = instance.rotation.sin_cos();
+
+        let radius = (half_size.x * half_size.x + half_size.y * half_size.y).sqrt();
+        let (min_x, max_x) = canvas.clamp_x(center.x - radius, center.x + radius);
+        let (min_y, max_y) = canvas.clamp_y(center.y - radius, center.y + radius);
+
+        for y in min_y .. max_y {
+            for x in min_x .. max_x {
+                let relative = Vector2::new(x as f32 + 0.5, y as f32 + 0.5) - center;
+                // From -1 to 1 across the instance
+                let point = Vector2::new(
+                    (cos * relative.x + sin * relative.y) / half_size.x,
+                    (cos * relative.y - sin * relative.x) / half_size.y,
+                );
+
+                if point.x.abs() > 1.0 || point.y.abs() > 1.0 {
+                    continue;
+                }
+
+                let colour = if instance.overlay_only == 1 {
+                    instance.overlay
+                } else {
+                    let uv = Vector2::new(
+                        instance.uv_top_left[0] + instance.uv_dimensions[0] * (point.x + 1.0) / 2.0,
+                        instance.uv_top_left[1] + instance.uv_dimensions[1] * (point.y + 1.0) / 2.0,
+                    );
+                    let texel = self.sample(uv);
+                    let overlay = instance.overlay;
+
+                    [
+                        mix(texel[0], overlay[0], overlay[3]),
+                        mix(texel[1], overlay[1], overlay[3]),
+                        mix(texel[2], overlay[2], overlay[3]),
+                        texel[3],
+                    ]
+                };
+
+                canvas.blend(x, y, colour);
+            }
+        }
+    }
+
+    // Nearest-neighbour sampling, like the sprite sampler.
+    fn sample(&self, uv: Vector2<f32>) -> [f32; 4] {
+        let (width, height) = self.texture.dimensions();
+        let x = ((uv.x * width as f32) as u32).min(width - 1);
+        let y = ((uv.y * height as f32) as u32).min(height - 1);
+        let pixel = self.texture.get_pixel(x, y).0;
+
+        [pixel[0] as f32 / 255.0, pixel[1] as f32 / 255.0, pixel[2] as f32 / 255.0, pixel[3] as f32 / 255.0]
+    }
+
+    fn draw_section(&self, canvas: &mut Canvas, section: &glyph_brush::OwnedSection<wgpu_glyph::Extra>, clip: (f32, f32)) {
+        let section = section.to_borrowed();
+        let geometry = glyph_brush::SectionGeometry {
+            screen_position: section.screen_position,
+            bounds: section.bounds,
+        };
+
+        for section_glyph in section.layout.calculate_glyphs(&self.fonts, &geometry, &section.text) {
+            let colour = section.text[section_glyph.section_index].extra.other.color;
+            let mut glyph = section_glyph.glyph;
+            // The text is pixelated, so glyphs sit on whole pixels.
+            glyph.position.x = glyph.position.x.round();
+            glyph.position.y = glyph.position.y.round();
+
+            if let Some(outlined) = self.fonts[section_glyph.font_id.0].outline_glyph(glyph) {
+                let bounds = outlined.px_bounds();
+
+                outlined.draw(|x, y, coverage| {
+                    let x = bounds.min.x + x as f32;
+                    let y = bounds.min.y + y as f32;
+
+                    // Pixelated text is either fully covered or not at all.
+                    if coverage >= 0.5 && x >= 0.0 && y >= clip.0 && y < clip.1 {
+                        canvas.blend(x as u32, y as u32, colour);
+                    }
+                });
+            }
+        }
+    }
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32) -> Self {
+        // The same as the colour the wgpu renderer clears the frame to.
+        let border = [0.5, 0.125, 0.125, 1.0];
+
+        Self {
+            width, height,
+            pixels: vec![border; (width * height) as usize],
+        }
+    }
+
+    fn clamp_x(&self, min: f32, max: f32) -> (u32, u32) {
+        (min.max(0.0) as u32, (max.ceil().max(0.0) as u32).min(self.width))
+    }
+
+    fn clamp_y(&self, min: f32, max: f32) -> (u32, u32) {
+        (min.max(0.0) as u32, (max.ceil().max(0.0) as u32).min(self.height))
+    }
+
+    // Uses the same blend state as the sprite pipeline.
+    fn blend(&mut self, x: u32, y: u32, colour: [f32; 4]) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        let pixel = &mut self.pixels[(y * self.width + x) as usize];
+        let alpha = colour[3];
+
+        for (channel, value) in pixel.iter_mut().zip(&colour).take(3) {
+            *channel = value * alpha + *channel * (1.0 - alpha);
+        }
+
+        pixel[3] = pixel[3].max(alpha);
+    }
+
+    fn into_image(self) -> image::RgbaImage {
+        let to_byte = |value: f32| (value.max(0.0).min(1.0) * 255.0).round() as u8;
+
+        image::RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let pixel = self.pixels[(y * self.width + x) as usize];
+            image::Rgba([to_byte(pixel[0]), to_byte(pixel[1]), to_byte(pixel[2]), to_byte(pixel[3])])
+        })
+    }
+}
@@ -1,16 +1,252 @@
 use winit::event::VirtualKeyCode;
 use cgmath::Vector2;
 use rand::Rng;
-use crate::components::Player;
+use crate::components::{Player, BombType, SpellCard, Pickup, PathEnd};
+use specs::{Entity, shrev::EventChannel};
 use std::borrow::Cow;
-use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
 
-#[derive(Clone, Copy, Debug)]
+fn load_toml<T: DeserializeOwned + Default>(filename: &str) -> T {
+    match std::fs::read(filename) {
+        Ok(vec) => match toml::from_slice(&vec) {
+            Ok(value) => value,
+            Err(err) => {
+                log::warn!("Failed to parse `{}` with: {}. Switching to the defaults.", filename, err);
+                T::default()
+            }
+        },
+        Err(err) => {
+            if !matches!(err.kind(), std::io::ErrorKind::NotFound) {
+                log::warn!("Failed to read `{}` with: {}. Switching to the defaults.", filename, err);
+            }
+            T::default()
+        }
+    }
+}
+
+// Saving can fail on platforms without a filesystem, such as wasm.
+fn save_toml<T: Serialize>(value: &T, filename: &str) {
+    let vec = toml::to_vec(value).unwrap();
+    if let Err(err) = std::fs::write(filename, vec) {
+        log::warn!("Failed to write `{}` with: {}", filename, err);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Stage {
     One,
     Two,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Lunatic,
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty::Normal
+    }
+}
+
+impl Difficulty {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Easy => Self::Normal,
+            Self::Normal => Self::Hard,
+            Self::Hard => Self::Lunatic,
+            Self::Lunatic => Self::Easy,
+        }
+    }
+
+    pub fn bullet_speed(self, speed: f32) -> f32 {
+        speed * match self {
+            Self::Easy => 0.75,
+            Self::Normal => 1.0,
+            Self::Hard => 1.15,
+            Self::Lunatic => 1.3,
+        }
+    }
+
+    // Multiplies the time between shots.
+    pub fn cooldown_scale(self) -> f32 {
+        match self {
+            Self::Easy => 1.5,
+            Self::Normal => 1.0,
+            Self::Hard => 0.8,
+            Self::Lunatic => 0.65,
+        }
+    }
+
+    // Always fires at least one bullet.
+    pub fn bullet_count(self, count: u16) -> u16 {
+        let multiplier = match self {
+            Self::Easy => 0.5,
+            Self::Normal => 1.0,
+            Self::Hard => 1.5,
+            Self::Lunatic => 2.0,
+        };
+
+        ((count as f32 * multiplier).round() as u16).max(1)
+    }
+
+    pub fn health(self, health: u32) -> u32 {
+        let multiplier = match self {
+            Self::Easy => 0.75,
+            Self::Normal => 1.0,
+            Self::Hard => 1.25,
+            Self::Lunatic => 1.5,
+        };
+
+        (health as f32 * multiplier).round() as u32
+    }
+}
+
+// Where a stage starts from. Practice mode can skip ahead.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StageStart {
+    Time(f32),
+    Boss { phase: usize },
+}
+
+impl StageStart {
+    fn name(self) -> String {
+        match self {
+            Self::Time(time) => format!("{}s", time),
+            Self::Boss { phase } => format!("Boss Phase {}", phase + 1),
+        }
+    }
+}
+
+// Set while playing a stage started from the practice menu.
+#[derive(Default)]
+pub struct Practice {
+    pub active: bool,
+    pub slow_motion: bool,
+}
+
+// Set while playing boss rush. `boss` indexes `stages::BOSS_RUSH`.
+#[derive(Default)]
+pub struct BossRush {
+    pub active: bool,
+    pub boss: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BossRushRecord {
+    pub score: u32,
+    pub time: f32,
+}
+
+// A table of records for each difficulty.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BossRushRecords {
+    easy: Vec<BossRushRecord>,
+    normal: Vec<BossRushRecord>,
+    hard: Vec<BossRushRecord>,
+    lunatic: Vec<BossRushRecord>,
+}
+
+impl BossRushRecords {
+    const MAX: usize = 10;
+
+    fn table(&self, difficulty: Difficulty) -> &Vec<BossRushRecord> {
+        match difficulty {
+            Difficulty::Easy => &self.easy,
+            Difficulty::Normal => &self.normal,
+            Difficulty::Hard => &self.hard,
+            Difficulty::Lunatic => &self.lunatic,
+        }
+    }
+
+    fn table_mut(&mut self, difficulty: Difficulty) -> &mut Vec<BossRushRecord> {
+        match difficulty {
+            Difficulty::Easy => &mut self.easy,
+            Difficulty::Normal => &mut self.normal,
+            Difficulty::Hard => &mut self.hard,
+            Difficulty::Lunatic => &mut self.lunatic,
+        }
+    }
+
+    // Returns the record's place in the difficulty's table, if it made it in.
+    // Higher scores come first, then faster times.
+    pub fn add(&mut self, record: BossRushRecord, difficulty: Difficulty) -> Option<usize> {
+        let table = self.table_mut(difficulty);
+
+        let place = table.iter()
+            .position(|other| record.score > other.score || (record.score == other.score && record.time < other.time))
+            .unwrap_or(table.len());
+
+        if place >= Self::MAX {
+            return None;
+        }
+
+        table.insert(place, record);
+        table.truncate(Self::MAX);
+        Some(place)
+    }
+
+    pub fn as_items(&self, difficulty: Difficulty) -> Vec<Item> {
+        let table = self.table(difficulty);
+
+        if table.is_empty() {
+            return vec![Item::unactive("No records yet")];
+        }
+
+        table.iter().enumerate()
+            .map(|(i, record)| Item::unactive_owned(format!("{}. {} - {}", i + 1, record.score, format_time(record.time))))
+            .collect()
+    }
+
+    pub fn load() -> Self {
+        load_toml("boss_rush.toml")
+    }
+
+    pub fn save(&self) {
+        save_toml(self, "boss_rush.toml");
+    }
+}
+
+// The best score for each stage on each difficulty.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct HighScores(HashMap<String, u32>);
+
+impl HighScores {
+    fn key(stage: Stage, difficulty: Difficulty) -> String {
+        format!("{:?} ({:?})", stage, difficulty)
+    }
+
+    // Returns the high score, including the new one.
+    pub fn add(&mut self, stage: Stage, difficulty: Difficulty, score: u32) -> u32 {
+        let high_score = self.0.entry(Self::key(stage, difficulty)).or_default();
+        *high_score = (*high_score).max(score);
+        *high_score
+    }
+
+    pub fn get(&self, stage: Stage, difficulty: Difficulty) -> Option<u32> {
+        self.0.get(&Self::key(stage, difficulty)).copied()
+    }
+
+    pub fn load() -> Self {
+        load_toml("high_scores.toml")
+    }
+
+    pub fn save(&self) {
+        save_toml(self, "high_scores.toml");
+    }
+}
+
+// Formats seconds as `m:ss.ss`.
+pub fn format_time(time: f32) -> String {
+    format!("{}:{:05.2}", (time / 60.0) as u32, time % 60.0)
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum PlayingState {
     Playing,
@@ -20,14 +256,17 @@ pub enum PlayingState {
 
 #[derive(Clone, Copy, Debug)]
 pub enum Mode {
-    Playing { stage: Stage, state: PlayingState, multiplayer: bool },
-    StageComplete { stage: Stage, selected: usize, multiplayer: bool },
-    Paused { selected: usize, stage: Stage, state: PlayingState, multiplayer: bool },
+    Playing { stage: Stage, state: PlayingState, multiplayer: bool, bomb: BombType },
+    StageComplete { stage: Stage, selected: usize, multiplayer: bool, bomb: BombType, score: u32, high_score: u32 },
+    Paused { selected: usize, stage: Stage, state: PlayingState, multiplayer: bool, bomb: BombType },
     MainMenu { selected: usize },
     Controls { selected: usize },
+    Settings { selected: usize },
     Quit,
-    Stages { selected: usize, multiplayer: bool },
+    Stages { selected: usize, multiplayer: bool, bomb: BombType, difficulty: Difficulty },
+    Practice { selected: usize, stage: Stage, start: StageStart, slow_motion: bool },
     StageLost { selected: usize },
+    BossRushComplete { selected: usize, score: u32, time: f32, difficulty: Difficulty, place: Option<usize> },
 }
 
 impl Default for Mode {
@@ -37,7 +276,7 @@ impl Default for Mode {
 }
 
 impl Mode {
-    pub fn as_menu(&mut self, ctrl_state: &ControlsState) -> Option<Menu> {
+    pub fn as_menu(&mut self, ctrl_state: &ControlsState, settings: &Settings, records: &BossRushRecords) -> Option<Menu> {
         match self {
             Mode::Paused { selected, .. } => Some(Menu {
                 title: "Paused",
@@ -48,18 +287,33 @@ impl Mode {
                 title: "Hectic",
                 items: vec![
                     Item::new("Play"),
+                    Item::new("Practice"),
                     Item::new("Controls"),
+                    Item::new("Settings"),
                     // lol
                     #[cfg(feature = "native")]
                     Item::new("Quit")
                 ],
                 selected,
             }),
-            Mode::Stages { selected, multiplayer } => Some(Menu {
+            Mode::Stages { selected, multiplayer, bomb, difficulty } => Some(Menu {
                 title: "Stages",
                 items: vec![
-                    Item::new("Stage One"), Item::new("Stage Two"),
+                    Item::new("Stage One"), Item::new("Stage Two"), Item::new("Boss Rush"),
                     Item::owned(format!("Mode: {}", if *multiplayer { "Multiplayer" } else { "Singleplayer" })),
+                    Item::owned(format!("Bomb: {:?}", bomb)),
+                    Item::owned(format!("Difficulty: {:?}", difficulty)),
+                    Item::new("Back")
+                ],
+                selected,
+            }),
+            Mode::Practice { selected, stage, start, slow_motion } => Some(Menu {
+                title: "Practice",
+                items: vec![
+                    Item::owned(format!("Stage: {:?}", stage)),
+                    Item::owned(format!("Start: {}", start.name())),
+                    Item::owned(format!("Slow Motion: {}", if *slow_motion { "On" } else { "Off" })),
+                    Item::new("Start"),
                     Item::new("Back")
                 ],
                 selected,
@@ -69,14 +323,21 @@ impl Mode {
                 items: ctrl_state.as_items(),
                 selected,
             }),
-            Mode::StageComplete { stage, selected, .. } => Some(Menu {
+            Mode::Settings { selected } => Some(Menu {
+                title: "Settings",
+                items: settings.as_items(),
+                selected,
+            }),
+            Mode::StageComplete { stage, selected, score, high_score, .. } => Some(Menu {
                 title: "Stage\nComplete!",
                 items: vec![
                     match stage {
                         Stage::One => Item::new("Continue to next stage"),
                         Stage::Two => Item::unactive("No Stage 3 yet!"),
                     },
-                    Item::new("Main Menu")
+                    Item::new("Main Menu"),
+                    Item::unactive_owned(format!("Score: {}", score)),
+                    Item::unactive_owned(format!("High Score: {}", high_score)),
                 ],
                 selected
             }),
@@ -85,6 +346,24 @@ impl Mode {
                 items: vec![Item::new("Main Menu")],
                 selected,
             }),
+            Mode::BossRushComplete { selected, score, time, difficulty, place } => Some(Menu {
+                title: "Boss Rush\nComplete!",
+                items: {
+                    let mut items = vec![
+                        Item::new("Main Menu"),
+                        Item::unactive_owned(format!("Score: {}", score)),
+                        Item::unactive_owned(format!("Time: {}", format_time(*time))),
+                        match place {
+                            Some(place) => Item::unactive_owned(format!("New record! #{}", *place + 1)),
+                            None => Item::unactive("No new record"),
+                        },
+                        Item::unactive_owned(format!("{:?} Records:", difficulty)),
+                    ];
+                    items.extend(records.as_items(*difficulty));
+                    items
+                },
+                selected,
+            }),
             _ => None,
         }
     }
@@ -94,6 +373,8 @@ impl Mode {
 pub struct ControlsState {
     pub pause: KeyState,
     pub debug: KeyState,
+    #[serde(default = "default_fullscreen_key")]
+    pub fullscreen: KeyState,
     single_player: PlayerControlsState,
     player_1: PlayerControlsState,
     player_2: PlayerControlsState,
@@ -105,6 +386,7 @@ impl ControlsState {
             Item::unactive("General:"),
             Item::owned(format!("pause: {:?}", self.pause.key)),
             Item::owned(format!("debug: {:?}", self.debug.key)),
+            Item::owned(format!("fullscreen: {:?}", self.fullscreen.key)),
         ];
 
         items.push(Item::unactive("Single Player:"));
@@ -125,6 +407,7 @@ impl ControlsState {
         self.player_2.press(key, pressed);
         self.pause.toggle(key, pressed);
         self.debug.toggle(key, pressed);
+        self.fullscreen.toggle(key, pressed);
     }
 
     pub fn get(&self, player: Player) -> &PlayerControlsState {
@@ -144,23 +427,11 @@ impl ControlsState {
     }
 
     pub fn load() -> Self {
-        match std::fs::read("controls.toml") {
-            Ok(vec) => match toml::from_slice(&vec) {
-                Ok(controls) => controls,
-                Err(err) => panic!("{}", err)
-            },
-            Err(err) => {
-                if !matches!(err.kind(), std::io::ErrorKind::NotFound) {
-                    log::warn!("Failed to read `controls.toml` with: {}. Switching to default controls.", err);
-                }
-                Self::default()
-            }
-        }
+        load_toml("controls.toml")
     }
 
     pub fn save(&self) {
-        let vec = toml::to_vec(self).unwrap();
-        std::fs::write("controls.toml", vec).unwrap();
+        save_toml(self, "controls.toml");
     }
 }
 
@@ -172,6 +443,208 @@ impl Default for ControlsState {
             player_2: PlayerControlsState::player_two(),
             pause: KeyState::new(VirtualKeyCode::P),
             debug: KeyState::new(VirtualKeyCode::Semicolon),
+            fullscreen: default_fullscreen_key(),
+        }
+    }
+}
+
+// Lets `controls.toml` files from before the fullscreen key existed still load.
+fn default_fullscreen_key() -> KeyState {
+    KeyState::new(VirtualKeyCode::F11)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WindowMode {
+    Windowed,
+    Borderless,
+    Exclusive,
+}
+
+impl WindowMode {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Windowed => Self::Borderless,
+            Self::Borderless => Self::Exclusive,
+            Self::Exclusive => Self::Windowed,
+        }
+    }
+
+    // What the fullscreen hotkey switches to.
+    pub fn toggled(self) -> Self {
+        match self {
+            Self::Windowed => Self::Borderless,
+            _ => Self::Windowed,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Scaling {
+    // Fill as much of the window as possible.
+    Fractional,
+    // Scale by the largest whole number that fits, so that every pixel is the same size.
+    Integer,
+    // Fill the window, but only blur the edges of pixels.
+    SharpBilinear,
+}
+
+impl Scaling {
+    fn next(self) -> Self {
+        match self {
+            Self::Fractional => Self::Integer,
+            Self::Integer => Self::SharpBilinear,
+            Self::SharpBilinear => Self::Fractional,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Fractional => "Fractional",
+            Self::Integer => "Integer",
+            Self::SharpBilinear => "Sharp Bilinear",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub always_show_hitbox: bool,
+    pub focused_shot: bool,
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub effects_volume: f32,
+    pub window_mode: WindowMode,
+    pub scaling: Scaling,
+    pub vsync: bool,
+    pub screen_shake: bool,
+    pub post_processing: PostProcessing,
+    // How long a player has to bomb after being hit in order to cancel the hit.
+    pub death_bomb_window: f32,
+}
+
+impl Settings {
+    // How much a volume changes each time it's selected in the menu.
+    const VOLUME_STEP: f32 = 0.1;
+    const DEATH_BOMB_WINDOW_STEP: f32 = 0.05;
+    const MIN_DEATH_BOMB_WINDOW: f32 = 0.1;
+    const MAX_DEATH_BOMB_WINDOW: f32 = 0.5;
+
+    pub fn as_items(&self) -> Vec<Item> {
+        let on_off = |value| if value { "On" } else { "Off" };
+
+        vec![
+            Item::owned(format!("Window: {:?}", self.window_mode)),
+            Item::owned(format!("Scaling: {}", self.scaling.name())),
+            Item::owned(format!("VSync: {}", on_off(self.vsync))),
+            Item::owned(format!("Master Volume: {:.0}%", self.master_volume * 100.0)),
+            Item::owned(format!("Music Volume: {:.0}%", self.music_volume * 100.0)),
+            Item::owned(format!("SFX Volume: {:.0}%", self.effects_volume * 100.0)),
+            Item::owned(format!("Always Show Hitbox: {}", on_off(self.always_show_hitbox))),
+            Item::owned(format!("Focused Shot: {}", on_off(self.focused_shot))),
+            Item::owned(format!("Screen Shake: {}", on_off(self.screen_shake))),
+            Item::owned(format!("CRT Scanlines: {}", on_off(self.post_processing.scanlines))),
+            Item::owned(format!("Bullet Bloom: {}", on_off(self.post_processing.bloom))),
+            Item::owned(format!("Colour Grading: {}", on_off(self.post_processing.colour_grading))),
+            Item::owned(format!("Bomb Flash: {}", on_off(self.post_processing.bomb_flash))),
+            Item::owned(format!("Death Bomb Window: {:.2}s", self.death_bomb_window)),
+            Item::new("Back"),
+        ]
+    }
+
+    // Changes the setting for the selected menu item. Returns false for the back item.
+    pub fn select(&mut self, selected: usize) -> bool {
+        match selected {
+            0 => self.window_mode = self.window_mode.next(),
+            1 => self.scaling = self.scaling.next(),
+            2 => self.vsync = !self.vsync,
+            3 => self.master_volume = Self::next_volume(self.master_volume),
+            4 => self.music_volume = Self::next_volume(self.music_volume),
+            5 => self.effects_volume = Self::next_volume(self.effects_volume),
+            6 => self.always_show_hitbox = !self.always_show_hitbox,
+            7 => self.focused_shot = !self.focused_shot,
+            8 => self.screen_shake = !self.screen_shake,
+            9 => self.post_processing.scanlines = !self.post_processing.scanlines,
+            10 => self.post_processing.bloom = !self.post_processing.bloom,
+            11 => self.post_processing.colour_grading = !self.post_processing.colour_grading,
+            12 => self.post_processing.bomb_flash = !self.post_processing.bomb_flash,
+            13 => self.death_bomb_window = Self::next_death_bomb_window(self.death_bomb_window),
+            _ => return false,
+        }
+
+        true
+    }
+
+    // Steps the volume up, wrapping around to silent after full volume.
+    fn next_volume(volume: f32) -> f32 {
+        let steps = (volume / Self::VOLUME_STEP).round() + 1.0;
+        if steps * Self::VOLUME_STEP > 1.0 + std::f32::EPSILON {
+            0.0
+        } else {
+            steps * Self::VOLUME_STEP
+        }
+    }
+
+    // Steps the death bomb window up, wrapping around to the shortest window after the longest.
+    fn next_death_bomb_window(window: f32) -> f32 {
+        let next = window + Self::DEATH_BOMB_WINDOW_STEP;
+        if next > Self::MAX_DEATH_BOMB_WINDOW + std::f32::EPSILON {
+            Self::MIN_DEATH_BOMB_WINDOW
+        } else {
+            next
+        }
+    }
+
+    pub fn load() -> Self {
+        load_toml("settings.toml")
+    }
+
+    pub fn save(&self) {
+        save_toml(self, "settings.toml");
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            always_show_hitbox: false,
+            focused_shot: true,
+            master_volume: 1.0,
+            music_volume: 0.7,
+            effects_volume: 0.8,
+            window_mode: WindowMode::Windowed,
+            scaling: Scaling::Fractional,
+            vsync: true,
+            screen_shake: true,
+            post_processing: PostProcessing::default(),
+            death_bomb_window: 0.25,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostProcessing {
+    pub scanlines: bool,
+    pub bloom: bool,
+    pub colour_grading: bool,
+    pub bomb_flash: bool,
+}
+
+impl PostProcessing {
+    // Whether any of the effects that are drawn over the offscreen texture are on. The bomb flash isn't one of them.
+    pub fn any(&self) -> bool {
+        self.scanlines || self.bloom || self.colour_grading
+    }
+}
+
+impl Default for PostProcessing {
+    fn default() -> Self {
+        Self {
+            scanlines: false,
+            bloom: false,
+            colour_grading: false,
+            bomb_flash: true,
         }
     }
 }
@@ -285,6 +758,92 @@ impl Default for GameTime {
     }
 }
 
+pub struct SpellCardAttempt {
+    pub card: SpellCard,
+    pub difficulty: Difficulty,
+    pub started: f32,
+    // Set if a player gets hit or bombs during the spell card.
+    pub failed: bool,
+}
+
+impl SpellCardAttempt {
+    pub fn time_remaining(&self, time: f32) -> f32 {
+        (self.started + self.card.duration - time).max(0.0)
+    }
+}
+
+#[derive(Default)]
+pub struct ActiveSpellCard(pub Option<SpellCardAttempt>);
+
+impl ActiveSpellCard {
+    pub fn fail(&mut self) {
+        if let Some(attempt) = &mut self.0 {
+            attempt.failed = true;
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    pub attempts: u32,
+    pub captures: u32,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CaptureHistory(HashMap<String, CaptureRecord>);
+
+impl CaptureHistory {
+    // Normal keeps the plain name so that history from before difficulties still counts.
+    fn key(card: SpellCard, difficulty: Difficulty) -> String {
+        match difficulty {
+            Difficulty::Normal => card.name.to_string(),
+            _ => format!("{} ({:?})", card.name, difficulty),
+        }
+    }
+
+    pub fn record_attempt(&mut self, card: SpellCard, difficulty: Difficulty) {
+        self.0.entry(Self::key(card, difficulty)).or_default().attempts += 1;
+    }
+
+    pub fn record_capture(&mut self, card: SpellCard, difficulty: Difficulty) {
+        self.0.entry(Self::key(card, difficulty)).or_default().captures += 1;
+    }
+
+    pub fn get(&self, card: SpellCard, difficulty: Difficulty) -> Option<&CaptureRecord> {
+        self.0.get(&Self::key(card, difficulty))
+    }
+
+    pub fn load() -> Self {
+        load_toml("spell_cards.toml")
+    }
+
+    pub fn save(&self) {
+        save_toml(self, "spell_cards.toml");
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum GameEvent {
+    // `killer` is the player responsible. Kills without one aren't worth any points.
+    EnemyKilled { enemy: Entity, position: Vector2<f32>, score: u32, boss: bool, killer: Option<Entity> },
+    PlayerHit { player: Entity },
+    BombUsed { player: Entity, bomb: BombType, death_bomb: bool },
+    BossPhaseChanged { phase: usize, spell_card_captured: bool },
+    ItemCollected { player: Entity, item: Pickup },
+    BulletGrazed { player: Entity },
+    // A bomb turned an enemy bullet into points.
+    BulletConverted { player: Entity, score: u32 },
+    // Bonuses shared between every player.
+    SpellCardCaptured { bonus: u32 },
+    WaveBonus { score: u32 },
+    ShotFired { player: Entity },
+    StageFinished { stage: Stage, won: bool, difficulty: Difficulty },
+    PathEnded { entity: Entity, on_end: PathEnd },
+}
+
+pub type GameEvents = EventChannel<GameEvent>;
+
 #[derive(Default)]
 pub struct PlayerPositions(pub Vec<Vector2<f32>>);
 
@@ -350,6 +909,13 @@ impl Item {
         }
     } 
 
+    pub fn unactive_owned(text: String) -> Self {
+        Self {
+            text: text.into(),
+            active: false,
+        }
+    }
+
     pub fn owned(text: String) -> Self {
         Self {
             text: text.into(),
@@ -357,3 +923,65 @@ impl Item {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(score: u32, time: f32) -> BossRushRecord {
+        BossRushRecord { score, time }
+    }
+
+    #[test]
+    fn records_are_ordered_by_score_then_time() {
+        let mut records = BossRushRecords::default();
+
+        assert_eq!(records.add(record(100, 60.0), Difficulty::Normal), Some(0));
+        assert_eq!(records.add(record(200, 90.0), Difficulty::Normal), Some(0));
+        assert_eq!(records.add(record(100, 50.0), Difficulty::Normal), Some(1));
+        assert_eq!(records.add(record(100, 70.0), Difficulty::Normal), Some(3));
+
+        assert_eq!(records.normal, vec![record(200, 90.0), record(100, 50.0), record(100, 60.0), record(100, 70.0)]);
+    }
+
+    #[test]
+    fn tables_are_truncated() {
+        let mut records = BossRushRecords::default();
+
+        for score in 1 ..= BossRushRecords::MAX as u32 {
+            assert!(records.add(record(score * 10, 60.0), Difficulty::Hard).is_some());
+        }
+
+        // Too low to make it in
+        assert_eq!(records.add(record(5, 60.0), Difficulty::Hard), None);
+        // Pushes the lowest record out
+        assert_eq!(records.add(record(15, 60.0), Difficulty::Hard), Some(BossRushRecords::MAX - 1));
+
+        assert_eq!(records.hard.len(), BossRushRecords::MAX);
+        assert_eq!(records.hard.last(), Some(&record(15, 60.0)));
+    }
+
+    #[test]
+    fn high_scores_are_kept_per_stage_and_difficulty() {
+        let mut high_scores = HighScores::default();
+
+        assert_eq!(high_scores.add(Stage::One, Difficulty::Normal, 500), 500);
+        assert_eq!(high_scores.add(Stage::One, Difficulty::Normal, 300), 500);
+        assert_eq!(high_scores.add(Stage::One, Difficulty::Hard, 300), 300);
+
+        assert_eq!(high_scores.get(Stage::One, Difficulty::Normal), Some(500));
+        assert_eq!(high_scores.get(Stage::Two, Difficulty::Normal), None);
+    }
+
+    #[test]
+    fn difficulties_have_their_own_tables() {
+        let mut records = BossRushRecords::default();
+
+        records.add(record(100, 60.0), Difficulty::Easy);
+        assert_eq!(records.add(record(50, 60.0), Difficulty::Lunatic), Some(0));
+
+        assert_eq!(records.easy.len(), 1);
+        assert_eq!(records.lunatic.len(), 1);
+        assert!(records.normal.is_empty());
+    }
+}
@@ -1,27 +1,126 @@
 use specs::prelude::*;
 use specs::world::LazyBuilder;
-use crate::{components::*, graphics, WIDTH, HEIGHT, ZERO, MIDDLE};
+use crate::{components::*, graphics, archetypes::Archetypes, resources::{Difficulty, Stage, StageStart}, WIDTH, HEIGHT, ZERO, MIDDLE};
 use cgmath::Vector2;
 use rand::Rng;
 
+// How a stage is played, chosen from the menus.
+#[derive(Clone, Copy)]
+pub struct StageSetup {
+    pub multiplayer: bool,
+    pub bomb: BombType,
+    pub difficulty: Difficulty,
+    pub start: StageStart,
+}
+
 struct EntityBuilder<'a> {
     entities: &'a Entities<'a>,
-    updater: &'a LazyUpdate
+    updater: &'a LazyUpdate,
+    archetypes: &'a Archetypes,
+    difficulty: Difficulty,
+    // Anything due to spawn before this has already been skipped over and is never built. That includes enemies which would
+    // still be on screen at this point: their paths depend on everything that happened before, so a practice start always
+    // begins with an empty screen rather than trying to reconstruct them.
+    skip_until: f32,
+    boss_phase: usize,
 }
 
-impl EntityBuilder<'_> {
+impl<'a> EntityBuilder<'a> {
+    fn new(entities: &'a Entities<'a>, updater: &'a LazyUpdate, archetypes: &'a Archetypes, stage: Stage, setup: StageSetup) -> Self {
+        let (skip_until, boss_phase) = match setup.start {
+            StageStart::Time(time) => (time, 0),
+            StageStart::Boss { phase } => (boss_time(stage), phase),
+        };
+
+        Self { entities, updater, archetypes, difficulty: setup.difficulty, skip_until, boss_phase }
+    }
+
     fn create_entity(&self) -> LazyBuilder<'_> {
         self.updater.create_entity(self.entities)
     } 
+
+    fn spawn(&self, archetype: &str, position: Vector2<f32>, start: f32) {
+        if !self.elapsed(start) {
+            self.archetypes.get(archetype).spawn(self.create_entity(), position, start, self.difficulty).build();
+        }
+    }
+
+    fn spawn_on_path(&self, archetype: &str, curve: FollowCurve, start: f32) {
+        if !self.elapsed(start) {
+            self.archetypes.get(archetype).spawn_on_path(self.create_entity(), curve, start, self.difficulty).build();
+        }
+    }
+
+    fn elapsed(&self, start: f32) -> bool {
+        start < self.skip_until
+    }
+}
+
+// When each stage's boss turns up.
+pub fn boss_time(stage: Stage) -> f32 {
+    match stage {
+        Stage::One => 55.0,
+        Stage::Two => 50.0,
+    }
+}
+
+pub fn boss_phases(stage: Stage) -> usize {
+    boss(stage, Difficulty::Normal).0.phases.len()
+}
+
+pub fn start(stage: Stage, entities: &Entities, updater: &LazyUpdate, archetypes: &Archetypes, setup: StageSetup, time: &mut f32) {
+    match stage {
+        Stage::One => stage_one(entities, updater, archetypes, setup, time),
+        Stage::Two => stage_two(entities, updater, archetypes, setup, time),
+    }
+}
+
+// The next choice of where to start a stage in the practice menu: every 5 seconds until the boss, then each boss phase.
+pub fn next_start(stage: Stage, start: StageStart) -> StageStart {
+    match start {
+        StageStart::Time(time) if time + 5.0 < boss_time(stage) => StageStart::Time(time + 5.0),
+        StageStart::Time(_) => StageStart::Boss { phase: 0 },
+        StageStart::Boss { phase } if phase + 1 < boss_phases(stage) => StageStart::Boss { phase: phase + 1 },
+        StageStart::Boss { .. } => StageStart::Time(0.0),
+    }
+}
+
+// Every boss, in the order boss rush fights them.
+pub const BOSS_RUSH: [Stage; 2] = [Stage::One, Stage::Two];
+
+// The break before each boss in boss rush.
+pub const BOSS_RUSH_INTERMISSION: f32 = 3.0;
+
+pub fn boss_rush(entities: &Entities, updater: &LazyUpdate, archetypes: &Archetypes, setup: StageSetup, time: &mut f32) {
+    let builder = &EntityBuilder::new(entities, updater, archetypes, BOSS_RUSH[0], setup);
+
+    *time = 0.0;
+    clear(builder);
+
+    create_background(builder, graphics::Image::NightSky, ZERO, ZERO, 0);
+    create_background(builder, graphics::Image::Clouds, ZERO, Vector2::new(0.0, 1.0), 1);
+    create_background(builder, graphics::Image::Clouds, Vector2::new(0.0, 1920.0), Vector2::new(0.0, 1.0), 1);
+    create_title(builder, "Boss\nRush");
+    create_players(builder, setup.multiplayer, setup.bomb);
+
+    spawn_boss(builder, BOSS_RUSH[0], BOSS_RUSH_INTERMISSION);
+}
+
+// Brings in the next boss rush boss once the last one is beaten. Players and backgrounds are left as they are.
+pub fn next_rush_boss(entities: &Entities, updater: &LazyUpdate, archetypes: &Archetypes, stage: Stage, setup: StageSetup, time: f32) {
+    let builder = &EntityBuilder::new(entities, updater, archetypes, stage, setup);
+
+    create_title(builder, "Next\nBoss");
+    spawn_boss(builder, stage, time + BOSS_RUSH_INTERMISSION);
 }
 
 fn clear(builder: &EntityBuilder) {
     builder.entities.join().for_each(|entity| builder.entities.delete(entity).unwrap());
 }
 
-pub fn stage_one(entities: &Entities, updater: &LazyUpdate, multiplayer: bool, time: &mut f32) {
+pub fn stage_one(entities: &Entities, updater: &LazyUpdate, archetypes: &Archetypes, setup: StageSetup, time: &mut f32) {
     let mut rng = rand::thread_rng();
-    let builder = &EntityBuilder { entities, updater };
+    let builder = &EntityBuilder::new(entities, updater, archetypes, Stage::One, setup);
 
     *time = 0.0;
     clear(builder);
@@ -29,7 +128,7 @@ pub fn stage_one(entities: &Entities, updater: &LazyUpdate, multiplayer: bool, t
     create_background(builder, graphics::Image::Clouds, ZERO, Vector2::new(0.0, 1.0), 1);
     create_background(builder, graphics::Image::Clouds, Vector2::new(0.0, 1920.0), Vector2::new(0.0, 1.0), 1);
     create_title(builder, "Stage\nOne");
-    create_players(builder, multiplayer);
+    create_players(builder, setup.multiplayer, setup.bomb);
 
     for start in float_iter(1.0, 6.0, 0.25) {
         bat_with_curve(builder, FollowCurve::horizontal(100.0, 300.0, true, 2.5), start);
@@ -48,119 +147,58 @@ pub fn stage_one(entities: &Entities, updater: &LazyUpdate, multiplayer: bool, t
     }
 
     for start in float_iter(15.0, 20.0, 0.5) {
-        bat_with_curve(builder, FollowCurve::horizontal(400.0, 600.0, true, 2.5), start)
+        bat_with_curve(builder, FollowCurve::horizontal(400.0, 600.0, true, 2.5), start);
     }
 
-    let rock_bullet = BulletSetup { image: Image::from(graphics::Image::RockBullet), speed: 2.8, colour: None };
-
     for x in [0.25, 0.5, 0.75].iter() {
-        let start = 24.0;
-
-        enemy(
-            builder,
-            Vector2::new(x * WIDTH, -50.0),
-            start,
-            15,
-            graphics::Image::Gargoyle,
-            Vector2::new(45.0, 25.0),
-        )
-            .with(FiringMove { speed: 2.5, return_time: start + 10.0, stop_time: start + 1.0 })
-            .with(FiresBullets::AtPlayer {
-                num_bullets: 3,
-                spread: 1.0,
-                cooldown: Cooldown::ready_at(1.0, start + 1.0 + rng.gen_range(-0.25, 0.5)),
-                setup: rock_bullet
-            })
-            .build();
+        builder.spawn("gargoyle", Vector2::new(x * WIDTH, -50.0), 24.0);
     }
 
     for x in [0.375, 0.625].iter() {
-        let start = 28.0;
-
-        enemy(
-            builder,
-            Vector2::new(x * WIDTH, -50.0),
-            start,
-            15,
-            graphics::Image::Gargoyle,
-            Vector2::new(45.0, 25.0),
-        )
-            .with(FiringMove { speed: 2.5, return_time: start + 10.0, stop_time: start + 1.0 })
-            .with(FiresBullets::AtPlayer {
-                num_bullets: 3,
-                spread: 1.0,
-                cooldown: Cooldown::ready_at(1.0, start + 1.0 + rng.gen_range(-0.25, 0.5)),
-                setup: rock_bullet
-            })
-            .build();
+        builder.spawn("gargoyle", Vector2::new(x * WIDTH, -50.0), 28.0);
     }
 
     for start in float_iter(25.0, 33.0, 0.25) {
         bat_with_curve(builder, FollowCurve::circular(200.0, 1000.0, 2.5), start);
     }
 
+    builder.spawn("gargoyle_roost", Vector2::new(WIDTH / 2.0, -50.0), 31.0);
+
     for start in float_iter(35.0, 50.0, 0.25) {
-        hell_bat(builder, start, Vector2::new(rng.gen_range(0.0, WIDTH), -50.0));
+        builder.spawn("hell_bat", Vector2::new(rng.gen_range(0.0, WIDTH), -50.0), start);
     }
 
     for start in float_iter(45.0, 50.0, 1.0) {
-        enemy_with_curve(
-            builder,
-            FollowCurve::horizontal(100.0, 300.0, true, 2.5),
-            start,
-            15,
-            graphics::Image::Gargoyle,
-            Vector2::new(45.0, 25.0),
-        )
-            .with(FiresBullets::AtPlayer {
-                num_bullets: 1,
-                spread: 0.0,
-                cooldown: Cooldown::ready_at(1.0, start + rng.gen_range(0.0, 0.5)),
-                setup: rock_bullet
-            })
-            .build();
+        builder.spawn_on_path("gargoyle_scout", FollowCurve::horizontal(100.0, 300.0, true, 2.5), start);
     }
 
-    boss_one(builder, 55.0);
-}
-
-fn hell_bat(builder: &EntityBuilder, start: f32, position: Vector2<f32>) {
-    builder.create_entity()
-        .with(Position(position))
-        .with(FrozenUntil(start))
-        .with(DieOffscreen)
-        .with(Enemy)
-        .with(Health(12))
-        .with(Image::from(graphics::Image::HellBat))
-        .with(Hitbox(Vector2::new(25.0, 20.0)))
-        .with(TargetPlayer(2.5))
-        .build();
+    spawn_boss(builder, Stage::One, boss_time(Stage::One));
+    // Enemies that spawned before this are left out, see `EntityBuilder::skip_until`.
+    *time = builder.skip_until;
 }
 
 fn bat_with_curve(builder: &EntityBuilder, curve: FollowCurve, start: f32) {
-    enemy_with_curve(builder, curve, start, 4, graphics::Image::Bat, Vector2::new(25.0, 20.0)).build();
+    builder.spawn_on_path("bat", curve, start);
 }
 
-fn enemy<'a>(builder: &'a EntityBuilder, position: Vector2<f32>, start: f32, health: u32, image: graphics::Image, hitbox: Vector2<f32>) -> LazyBuilder<'a> {
-    builder.create_entity()
-        .with(Position(position))
-        .with(FrozenUntil(start))
-        .with(DieOffscreen)
-        .with(Enemy)
-        .with(Health(health))
-        .with(Image::from(image))
-        .with(Hitbox(hitbox))
-}
+// Spawns `count` enemies following the same path in a formation, as a wave that is rewarded when every member is killed.
+fn formation(
+    builder: &EntityBuilder, archetype: &str, leader: FollowCurve, formation: Formation, count: usize, reward: WaveReward, start: f32,
+) {
+    // Otherwise the empty wave would be rewarded straight away
+    if builder.elapsed(start) {
+        return;
+    }
+
+    let wave = builder.create_entity()
+        .with(Wave::new(reward, count as u32))
+        .build();
 
-fn enemy_with_curve<'a>(builder: &'a EntityBuilder, curve: FollowCurve, start: f32, health: u32, image: graphics::Image, hitbox: Vector2<f32>) -> LazyBuilder<'a> {
-    enemy(
-        builder,
-        curve.start(),
-        start,
-        health,
-        image,
-        hitbox,
-    ).with(curve)
+    for offset in formation.offsets(count) {
+        builder.archetypes.get(archetype).spawn_on_path(builder.create_entity(), leader.clone().with_offset(offset), start, builder.difficulty)
+            .with(WaveMember(wave))
+            .build();
+    }
 }
 
 fn float_iter(start: f32, end: f32, step: f32) -> impl Iterator<Item = f32> {
@@ -173,17 +211,17 @@ fn float_iter(start: f32, end: f32, step: f32) -> impl Iterator<Item = f32> {
         .take_while(move |item| *item < end)
 }
 
-fn create_players(builder: &EntityBuilder, two_players: bool) {
+fn create_players(builder: &EntityBuilder, two_players: bool, bomb: BombType) {
     if two_players {
         let offset = Vector2::new(20.0, 0.0);
-        create_player(builder, Player::One, MIDDLE - offset);
-        create_player(builder, Player::Two, MIDDLE + offset);
+        create_player(builder, Player::One, MIDDLE - offset, bomb);
+        create_player(builder, Player::Two, MIDDLE + offset, bomb);
     } else {
-        create_player(builder, Player::Single, MIDDLE);
+        create_player(builder, Player::Single, MIDDLE, bomb);
     }
 }
 
-fn create_player(builder: &EntityBuilder, player: Player, position: Vector2<f32>) {
+fn create_player(builder: &EntityBuilder, player: Player, position: Vector2<f32>, bomb: BombType) {
     builder.create_entity()
             .with(Position(position))
             .with(Image::from(graphics::Image::Player))
@@ -194,6 +232,8 @@ fn create_player(builder: &EntityBuilder, player: Player, position: Vector2<f32>
             .with(Health(3))
             .with(Invulnerability::new())
             .with(PowerBar(0))
+            .with(bomb)
+            .with(Score(0))
             .build();
 }
 
@@ -214,9 +254,9 @@ fn create_background(builder: &EntityBuilder, image: graphics::Image, position:
         .build();
 }
 
-pub fn stage_two(entities: &Entities, updater: &LazyUpdate, multiplayer: bool, time: &mut f32) {
+pub fn stage_two(entities: &Entities, updater: &LazyUpdate, archetypes: &Archetypes, setup: StageSetup, time: &mut f32) {
     let mut rng = rand::thread_rng();
-    let builder = &EntityBuilder { entities, updater };
+    let builder = &EntityBuilder::new(entities, updater, archetypes, Stage::Two, setup);
 
     *time = 0.0;
     clear(builder);
@@ -226,53 +266,67 @@ pub fn stage_two(entities: &Entities, updater: &LazyUpdate, multiplayer: bool, t
     create_background(builder, graphics::Image::Fog, Vector2::new(0.0, 1920.0), Vector2::new(0.0, 0.5), 1);
     create_background(builder, graphics::Image::Darkness, ZERO, ZERO, 2);
     create_title(builder, "Stage\nTwo");
-    create_players(builder, multiplayer);
+    create_players(builder, setup.multiplayer, setup.bomb);
 
     let spectre_speed = 10.0 / 3.0;
 
     for start in float_iter(5.0, 20.0, 0.5) {
-        let setup = BulletSetup {
-            image: Image::from(graphics::Image::DarkBullet),
-            speed: spectre_speed,
-            colour: None
-        };
+        let curve = FollowCurve::horizontal(rng.gen_range(0.0, HEIGHT / 2.0), rng.gen_range(0.0, HEIGHT / 2.0), true, spectre_speed);
+        builder.spawn_on_path("spectre", curve, start);
+    }
 
-        enemy_with_curve(
-            builder,
-            FollowCurve::horizontal(rng.gen_range(0.0, HEIGHT / 2.0), rng.gen_range(0.0, HEIGHT / 2.0), true, spectre_speed),
-            start, 8, graphics::Image::Spectre, Vector2::new(30.0, 30.0),
-        )
-            .with(FiresBullets::AtPlayer { num_bullets: 1, spread: 0.0, cooldown: Cooldown::ready_at(1.0, rng.gen_range(start, start + 1.0)), setup })
-            .build();
+    for (i, start) in float_iter(8.0, 20.0, 4.0).enumerate() {
+        let y = 100.0 + i as f32 * 50.0;
+
+        formation(builder, "spectre_ring", FollowCurve::horizontal(y, y, i % 2 == 0, spectre_speed), Formation::Circle { radius: 40.0 }, 6, WaveReward::Score(5000), start);
+    }
+
+    formation(builder, "flying_skull", FollowCurve::vertical(0.2, 0.8, spectre_speed), Formation::Line { spacing: Vector2::new(30.0, -10.0) }, 5, WaveReward::Item(Pickup::BombPiece), 22.0);
+
+    for (x, start) in [(0.3, 24.0), (0.7, 30.0), (0.5, 36.0)].iter() {
+        builder.spawn("grave", Vector2::new(x * WIDTH, -50.0), *start);
     }
 
     for start in float_iter(25.0, 45.0, 0.5) {
-        flying_skull(builder, start, Vector2::new(rng.gen_range(0.0, WIDTH), -25.0));
+        builder.spawn("flying_skull", Vector2::new(rng.gen_range(0.0, WIDTH), -25.0), start);
         
         if start >= 30.0 {
-            flying_skull(builder, start, Vector2::new(rng.gen_range(0.0, WIDTH), -25.0));
-            flying_skull(builder, start, Vector2::new(-25.0, rng.gen_range(0.0, HEIGHT / 2.0)));
-            flying_skull(builder, start, Vector2::new(WIDTH + 25.0, rng.gen_range(0.0, HEIGHT / 2.0)));
+            builder.spawn("flying_skull", Vector2::new(rng.gen_range(0.0, WIDTH), -25.0), start);
+            builder.spawn("flying_skull", Vector2::new(-25.0, rng.gen_range(0.0, HEIGHT / 2.0)), start);
+            builder.spawn("flying_skull", Vector2::new(WIDTH + 25.0, rng.gen_range(0.0, HEIGHT / 2.0)), start);
         }
     }
 
-    boss_two(builder, 50.0);
+    spawn_boss(builder, Stage::Two, boss_time(Stage::Two));
+    // Enemies that spawned before this are left out, see `EntityBuilder::skip_until`.
+    *time = builder.skip_until;
 }
 
-fn flying_skull(builder: &EntityBuilder, start: f32, position: Vector2<f32>) {
+// Bosses can be built on their own, without the rest of their stage.
+pub fn boss(stage: Stage, difficulty: Difficulty) -> (Boss, graphics::Image) {
+    match stage {
+        Stage::One => (boss_one(difficulty), graphics::Image::BossOne),
+        Stage::Two => (boss_two(difficulty), graphics::Image::BossTwo),
+    }
+}
+
+fn spawn_boss(builder: &EntityBuilder, stage: Stage, start: f32) {
+    let (mut boss, image) = boss(stage, builder.difficulty);
+    boss.start_at_phase(builder.boss_phase);
+
     builder.create_entity()
-        .with(Position(position))
+        .with(Position(Vector2::new(WIDTH / 2.0, -50.0)))
         .with(FrozenUntil(start))
         .with(DieOffscreen)
         .with(Enemy)
-        .with(Health(4))
-        .with(Image::from(graphics::Image::FlyingSkull))
-        .with(Hitbox(Vector2::new(25.0, 25.0)))
-        .with(TargetPlayer(10.0 / 3.0))
+        .with(Health(boss.phase_start_health()))
+        .with(Image::from(image))
+        .with(Hitbox(Vector2::new(30.0, 40.0)))
+        .with(boss)
         .build();
 }
 
-fn boss_one(builder: &EntityBuilder, start: f32) {
+fn boss_one(difficulty: Difficulty) -> Boss {
     let speed = 10.0 / 3.0;
     let orange_bullet = BulletSetup {
         image: Image::from(graphics::Image::Sword),
@@ -280,51 +334,61 @@ fn boss_one(builder: &EntityBuilder, start: f32) {
         colour: None
     };
 
-    builder.create_entity()
-        .with(Position(Vector2::new(WIDTH / 2.0, -50.0)))
-        .with(FrozenUntil(start))
-        .with(DieOffscreen)
-        .with(Enemy)
-        .with(Health(300))
-        .with(Image::from(graphics::Image::BossOne))
-        .with(Hitbox(Vector2::new(30.0, 40.0)))
-        .with(Boss {
-            max_health: 300,
-            current_move: 0,
-            move_timer: 0.0,
+    Boss::new(difficulty.health(300), vec![
+        BossPhase {
+            health_threshold: difficulty.health(150),
+            duration: Some(30.0),
+            spell_card: None,
             moves: vec![
                 BossMove {
-                    position: Vector2::new(100.0, 100.0),
+                    movement: BossMovement::To(Vector2::new(100.0, 100.0)),
                     fires: FiresBullets::Multiple(vec![
                         FiresBullets::Arc { initial_rotation: 0.0, spread: 2.0, number_to_fire: 20, fired_at_once: 1, fired_so_far: 0, cooldown: Cooldown::new(0.05), setup: orange_bullet },
                         FiresBullets::Arc { initial_rotation: 2.0, spread: -2.0, number_to_fire: 20, fired_at_once: 1, fired_so_far: 0, cooldown: Cooldown::new(0.05), setup: orange_bullet }
                     ]),
                     duration: 4.0,
+                    fire_while_moving: false,
                 },
                 BossMove {
-                    position: Vector2::new(150.0, 150.0),
+                    movement: BossMovement::Eased { position: Vector2::new(150.0, 150.0), time: 1.5, easing: Easing::InOutSine },
                     fires: FiresBullets::Multiple(vec![
                         FiresBullets::AtPlayer { num_bullets: 3, spread: 1.0, cooldown: Cooldown::new(0.75), setup: orange_bullet },
                         FiresBullets::Circle { sides: 4, rotation_per_fire: 0.5, rotation: 0.0, cooldown: Cooldown::new(0.1), setup: orange_bullet }
                     ]),
                     duration: 6.0,
+                    fire_while_moving: false,
                 },
+            ]
+        },
+        BossPhase {
+            health_threshold: 0,
+            duration: None,
+            spell_card: Some(SpellCard { name: "Blade Sign \"Whirling Swords\"", bonus: 50000, duration: 40.0 }),
+            moves: vec![
                 BossMove {
-                    position: Vector2::new(WIDTH / 2.0, 100.0),
+                    movement: BossMovement::To(Vector2::new(WIDTH / 2.0, 100.0)),
                     fires: FiresBullets::Circle { sides: 6, rotation_per_fire: 0.2, rotation: 0.0, cooldown: Cooldown::new(0.1), setup: orange_bullet },
                     duration: 6.0,
+                    fire_while_moving: false,
                 },
                 BossMove {
-                    position: Vector2::new(400.0, 200.0),
+                    movement: BossMovement::To(Vector2::new(400.0, 200.0)),
                     fires: FiresBullets::AtPlayer { num_bullets: 5, spread: 0.5, cooldown: Cooldown::new(0.25), setup: orange_bullet },
                     duration: 2.0,
+                    fire_while_moving: false,
+                },
+                BossMove {
+                    movement: BossMovement::Orbit { center: Vector2::new(WIDTH / 2.0, 150.0), radius: 80.0, speed: 1.5 },
+                    fires: FiresBullets::AtPlayer { num_bullets: 3, spread: 0.5, cooldown: Cooldown::new(0.5), setup: orange_bullet },
+                    duration: 6.0,
+                    fire_while_moving: true,
                 },
             ]
-        })
-        .build();
+        },
+    ])
 }
 
-fn boss_two(builder: &EntityBuilder, start: f32) {
+fn boss_two(difficulty: Difficulty) -> Boss {
     let speed = 10.0 / 3.0;
     let dark_bullet = BulletSetup {
         image: Image::from(graphics::Image::DarkBullet),
@@ -340,52 +404,74 @@ fn boss_two(builder: &EntityBuilder, start: f32) {
     let pi = std::f32::consts::PI;
 
 
-    builder.create_entity()
-        .with(Position(Vector2::new(WIDTH / 2.0, -50.0)))
-        .with(FrozenUntil(start))
-        .with(DieOffscreen)
-        .with(Enemy)
-        .with(Health(400))
-        .with(Image::from(graphics::Image::BossTwo))
-        .with(Hitbox(Vector2::new(30.0, 40.0)))
-        .with(Boss {
-            max_health: 400,
-            current_move: 0,
-            move_timer: 0.0,
+    Boss::new(difficulty.health(400), vec![
+        BossPhase {
+            health_threshold: difficulty.health(150),
+            duration: Some(40.0),
+            spell_card: None,
             moves: vec![
                 BossMove {
-                    position: Vector2::new(WIDTH / 2.0, 150.0),
+                    movement: BossMovement::To(Vector2::new(WIDTH / 2.0, 150.0)),
                     fires: FiresBullets::Arc { initial_rotation: pi / 2.0, spread: pi * 2.0, number_to_fire: 100, fired_at_once: 2, fired_so_far: 0, cooldown: Cooldown::new(0.015), setup: dark_bullet },
                     duration: 3.0,
+                    fire_while_moving: false,
                 },
                 BossMove {
-                    position: Vector2::new(WIDTH / 2.0 - 50.0, 160.0),
+                    movement: BossMovement::To(Vector2::new(WIDTH / 2.0 - 50.0, 160.0)),
                     fires: FiresBullets::Arc { initial_rotation: pi / 2.0, spread: -pi * 2.0, number_to_fire: 100, fired_at_once: 2, fired_so_far: 0, cooldown: Cooldown::new(0.015), setup: dark_bullet },
                     duration: 3.0,
+                    fire_while_moving: false,
                 },
                 BossMove {
-                    position: Vector2::new(WIDTH / 2.0 + 50.0, 170.0),
+                    movement: BossMovement::To(Vector2::new(WIDTH / 2.0 + 50.0, 170.0)),
                     duration: 5.0,
+                    fire_while_moving: false,
                     fires: FiresBullets::Multiple(vec![
                         FiresBullets::Arc { initial_rotation: 0.0, spread: 2.0 * pi, number_to_fire: 101, fired_at_once: 2, fired_so_far: 0, cooldown: Cooldown::new(0.03), setup: dark_bullet },
                         FiresBullets::Arc { initial_rotation: 2.0 * pi, spread: 2.0 * -pi, number_to_fire: 101, fired_at_once: 2, fired_so_far: 0, cooldown: Cooldown::new(0.03), setup: dark_bullet }
                     ])
                 },
+                BossMove {
+                    movement: BossMovement::Curve(
+                        FollowCurve::new(SplinePath::catmull_rom(&[
+                            Vector2::new(-200.0, 300.0), Vector2::new(100.0, 120.0), Vector2::new(WIDTH / 2.0, 200.0),
+                            Vector2::new(WIDTH - 100.0, 120.0), Vector2::new(WIDTH + 200.0, 300.0),
+                        ]), speed)
+                            .with_pause(1, 0.5)
+                    ),
+                    fires: FiresBullets::Circle { sides: 5, rotation_per_fire: 0.3, rotation: 0.0, cooldown: Cooldown::new(0.2), setup: dark_bullet },
+                    duration: 4.0,
+                    fire_while_moving: true,
+                },
+                BossMove {
+                    movement: BossMovement::TrackPlayer { y: 120.0, speed: 2.0 },
+                    fires: FiresBullets::AtPlayer { num_bullets: 3, spread: 0.3, cooldown: Cooldown::new(0.4), setup: dark_bullet },
+                    duration: 5.0,
+                    fire_while_moving: true,
+                },
+            ]
+        },
+        BossPhase {
+            health_threshold: 0,
+            duration: None,
+            spell_card: Some(SpellCard { name: "Grave Sign \"Violet Spiral\"", bonus: 80000, duration: 45.0 }),
+            moves: vec![
                 /*BossMove {
-                    position: Vector2::new(100.0, 100.0),
+                    movement: BossMovement::To(Vector2::new(100.0, 100.0)),
                     fires: FiresBullets::Multiple(vec![
                         FiresBullets::Arc { initial_rotation: pi / 2.0, spread: -pi * 2.0, number_to_fire: 100, fired_at_once: 2, fired_so_far: 0, cooldown: Cooldown::new(0.015), setup: purple_bullet },
                         FiresBullets::AtPlayer { num_bullets: 3, spread: 0.1, cooldown: Cooldown::new(0.2), setup: dark_bullet }
                     ]),
                     duration: 5.0,
+                    fire_while_moving: false,
                 },*/
                 BossMove {
-                    position: Vector2::new(100.0, 100.0),
+                    movement: BossMovement::To(Vector2::new(100.0, 100.0)),
                     fires: FiresBullets::Arc { initial_rotation: pi / 2.0, spread: 10.0 * -pi * 2.0, number_to_fire: 777, fired_at_once: 1, fired_so_far: 0, cooldown: Cooldown::new(0.015), setup: purple_bullet },
                     duration: 10.0,
+                    fire_while_moving: false,
                 },
-                
             ]
-        })
-        .build();
+        },
+    ])
 }
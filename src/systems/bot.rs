@@ -0,0 +1,113 @@
+// A simple bot for playing stages without a person at the keyboard.
+// Each tick it tries every move, predicts where the player and the enemies will be over the next few ticks,
+// and picks the move with the least danger.
+
+use specs::prelude::*;
+use cgmath::{Vector2, MetricSpace};
+use crate::{WIDTH, HEIGHT, resources::*, components::*};
+use super::PLAYER_SPEED;
+
+// How many ticks ahead the bot looks when checking a move.
+const LOOKAHEAD: u32 = 20;
+// How much room the bot tries to leave between its hitbox and anything dangerous.
+const SAFETY_MARGIN: f32 = 8.0;
+// Where the bot drifts back to when nothing is in the way.
+const HOME_HEIGHT: f32 = HEIGHT * 0.8;
+// How strongly the bot prefers to line up with enemies, compared to avoiding danger.
+const HOME_WEIGHT: f32 = 0.01;
+
+const DIRECTIONS: [(f32, f32); 9] = [
+    (0.0, 0.0),
+    (-1.0, 0.0), (1.0, 0.0), (0.0, -1.0), (0.0, 1.0),
+    (-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0),
+];
+
+struct Threat {
+    position: Vector2<f32>,
+    velocity: Vector2<f32>,
+    radius: f32,
+}
+
+impl Threat {
+    fn position_at(&self, tick: u32) -> Vector2<f32> {
+        self.position + self.velocity * tick as f32
+    }
+}
+
+pub struct BotControl;
+
+impl<'a> System<'a> for BotControl {
+    type SystemData = (
+        Entities<'a>, Write<'a, ControlsState>, ReadStorage<'a, Player>, ReadStorage<'a, Bot>,
+        ReadStorage<'a, Position>, ReadStorage<'a, Velocity>, ReadStorage<'a, Hitbox>, ReadStorage<'a, Enemy>,
+        ReadStorage<'a, FrozenUntil>, ReadStorage<'a, BeenOnscreen>, ReadStorage<'a, PendingHit>,
+    );
+
+    fn run(&mut self, (entities, mut ctrl_state, player, bot, pos, velocity, hitbox, enemy, frozen, onscreen, pending_hit): Self::SystemData) {
+        let threats: Vec<Threat> = (&entities, &pos, &hitbox, &enemy, !&frozen).join()
+            .map(|(entity, pos, hitbox, _, _)| Threat {
+                position: pos.0,
+                velocity: velocity.get(entity).map(|velocity| velocity.0).unwrap_or_else(|| Vector2::new(0.0, 0.0)),
+                radius: hitbox.0.x.max(hitbox.0.y) / 2.0,
+            })
+            .collect();
+
+        // Enemies (as opposed to bullets) have a hitbox
+        let targets: Vec<Vector2<f32>> = (&pos, &hitbox, &enemy, &onscreen, !&frozen).join()
+            .filter(|(_, hitbox, ..)| hitbox.0 != Vector2::new(0.0, 0.0))
+            .map(|(pos, ..)| pos.0)
+            .collect();
+
+        for (entity, player, pos, hitbox, _) in (&entities, &player, &pos, &hitbox, &bot).join() {
+            let radius = hitbox.0.x.max(hitbox.0.y) / 2.0 + SAFETY_MARGIN;
+
+            let target_x = targets.iter()
+                .min_by(|a, b| (a.x - pos.0.x).abs().partial_cmp(&(b.x - pos.0.x).abs()).unwrap())
+                .map(|target| target.x)
+                .unwrap_or(WIDTH / 2.0);
+            let home = Vector2::new(target_x, HOME_HEIGHT);
+
+            let mut best = (f32::MAX, (0.0, 0.0), false);
+
+            for &focused in &[false, true] {
+                let speed = if focused { PLAYER_SPEED / 2.0 } else { PLAYER_SPEED };
+
+                for &(x, y) in DIRECTIONS.iter() {
+                    let step = Vector2::new(x, y) * speed;
+                    let mut danger = 0.0;
+                    let mut position = pos.0;
+
+                    for tick in 1 ..= LOOKAHEAD {
+                        position.x = (position.x + step.x).max(0.0).min(WIDTH);
+                        position.y = (position.y + step.y).max(0.0).min(HEIGHT);
+
+                        for threat in &threats {
+                            let reach = radius + threat.radius;
+                            if threat.position_at(tick).distance2(position) < reach * reach {
+                                // Sooner collisions are worse
+                                danger += (LOOKAHEAD + 1 - tick) as f32;
+                            }
+                        }
+                    }
+
+                    let score = danger + position.distance(home) * HOME_WEIGHT;
+
+                    if score < best.0 {
+                        best = (score, (x, y), focused);
+                    }
+                }
+            }
+
+            let (_, (x, y), focused) = best;
+            let controls = ctrl_state.get_mut(*player);
+            controls.left.pressed = x < 0.0;
+            controls.right.pressed = x > 0.0;
+            controls.up.pressed = y < 0.0;
+            controls.down.pressed = y > 0.0;
+            controls.slow_movement.pressed = focused;
+            controls.fire.pressed = true;
+            // Only bomb to cancel a hit
+            controls.bomb.pressed = pending_hit.contains(entity);
+        }
+    }
+}
@@ -1,64 +1,70 @@
 use specs::prelude::*;
 use cgmath::{Vector2, MetricSpace, InnerSpace};
-use rand::{Rng, rngs::ThreadRng};
-use crate::{WIDTH, HEIGHT, resources::*, components::*, graphics::Image as GraphicsImage};
-use super::{is_touching, build_bullet, build_explosion};
+use rand::Rng;
+use crate::{resources::*, components::*};
+use super::{is_touching, build_bullet, build_explosion, build_item, Shooter};
+
+// How close an enemy bullet has to pass to a player to count as a graze.
+const GRAZE_RADIUS: f32 = 20.0;
 
 pub struct FireBullets;
 
 impl<'a> System<'a> for FireBullets {
     type SystemData = (
         Entities<'a>, ReadStorage<'a, Position>, WriteStorage<'a, FiresBullets>, ReadStorage<'a, BeenOnscreen>,
-        Read<'a, LazyUpdate>, Read<'a, GameTime>, Read<'a, PlayerPositions>,
+        Read<'a, LazyUpdate>, Read<'a, GameTime>, Read<'a, PlayerPositions>, Read<'a, Difficulty>,
     );
 
-    fn run(&mut self, (entities, pos, mut fires, onscreen, updater, time, player_positions): Self::SystemData) {
-        let mut rng = rand::thread_rng();
-
+    fn run(&mut self, (entities, pos, mut fires, onscreen, updater, time, player_positions, difficulty): Self::SystemData) {
         for (pos, mut fires, _) in (&pos, &mut fires, &onscreen).join() {
-            handle_fires_bullets(&entities, &updater, &mut fires, time.total_time, &player_positions, &mut rng, pos.0);
+            handle_fires_bullets(&entities, &updater, &mut fires, time.total_time, &player_positions, pos.0, *difficulty);
         }
     }
 }
 
+// The difficulty is applied as the bullets are fired, so patterns are written for normal difficulty.
 pub fn handle_fires_bullets(
     entities: &Entities, updater: &LazyUpdate, fires: &mut FiresBullets,
-    total_time: f32, player_positions: &PlayerPositions, rng: &mut ThreadRng,
-    pos: Vector2<f32>,
+    total_time: f32, player_positions: &PlayerPositions, pos: Vector2<f32>, difficulty: Difficulty,
 ) {
     match fires {
-        FiresBullets::AtPlayer { num_bullets, spread, cooldown, setup } => if cooldown.is_ready(total_time) {
-            let player = player_positions.random(rng);
+        FiresBullets::AtPlayer { num_bullets, spread, cooldown, setup } => if cooldown.is_ready_scaled(total_time, difficulty.cooldown_scale()) {
+            let player = player_positions.random(&mut rand::thread_rng());
+            let num_bullets = difficulty.bullet_count(*num_bullets);
+            let speed = difficulty.bullet_speed(setup.speed);
 
             // Get the rotation to the player
             let rotation = (player.y - pos.y).atan2(player.x - pos.x);
 
-            for i in 0 .. *num_bullets {
-                let mid_point = (*num_bullets - 1) as f32 / 2.0;
-                let rotation_difference = *spread * (mid_point - i as f32) / *num_bullets as f32;
+            for i in 0 .. num_bullets {
+                let mid_point = (num_bullets - 1) as f32 / 2.0;
+                let rotation_difference = *spread * (mid_point - i as f32) / num_bullets as f32;
 
                 let rotation = rotation + rotation_difference;
-                build_bullet(entities, updater, pos, setup.image, Vector2::new(rotation.cos(), rotation.sin()) * setup.speed, true, setup.colour);
+                build_bullet(entities, updater, pos, setup.image, Vector2::new(rotation.cos(), rotation.sin()) * speed, Shooter::Enemy, setup.colour);
             }
         },
-        FiresBullets::Circle { sides, rotation, rotation_per_fire, cooldown, setup } => if cooldown.is_ready(total_time) {
-            for side in 0 .. *sides {
-                let rotation = (side as f32 / *sides as f32) * std::f32::consts::PI * 2.0 + *rotation;
-                build_bullet(entities, updater, pos, setup.image, Vector2::new(rotation.cos(), rotation.sin()) * setup.speed, true, setup.colour);
+        FiresBullets::Circle { sides, rotation, rotation_per_fire, cooldown, setup } => if cooldown.is_ready_scaled(total_time, difficulty.cooldown_scale()) {
+            let sides = difficulty.bullet_count(*sides);
+            let speed = difficulty.bullet_speed(setup.speed);
+
+            for side in 0 .. sides {
+                let rotation = (side as f32 / sides as f32) * std::f32::consts::PI * 2.0 + *rotation;
+                build_bullet(entities, updater, pos, setup.image, Vector2::new(rotation.cos(), rotation.sin()) * speed, Shooter::Enemy, setup.colour);
             }
 
             *rotation += *rotation_per_fire;
         },
-        FiresBullets::Arc { initial_rotation, spread, fired_at_once, number_to_fire, fired_so_far, cooldown, setup } => if cooldown.is_ready(total_time) {
-            for _ in 0 .. *fired_at_once {
+        FiresBullets::Arc { initial_rotation, spread, fired_at_once, number_to_fire, fired_so_far, cooldown, setup } => if cooldown.is_ready_scaled(total_time, difficulty.cooldown_scale()) {
+            for _ in 0 .. difficulty.bullet_count(*fired_at_once) {
                 if *fired_so_far < *number_to_fire {
                     let rotation = *initial_rotation + *spread * (*fired_so_far as f32 / *number_to_fire as f32);
-                    build_bullet(entities, updater, pos, setup.image, Vector2::new(rotation.cos(), rotation.sin()) * setup.speed, true, setup.colour);
+                    build_bullet(entities, updater, pos, setup.image, Vector2::new(rotation.cos(), rotation.sin()) * difficulty.bullet_speed(setup.speed), Shooter::Enemy, setup.colour);
                     *fired_so_far += 1;
                 }
             }
         },
-        FiresBullets::Multiple(vec) => for fires in vec { handle_fires_bullets(entities, updater, fires, total_time, player_positions, rng, pos); }
+        FiresBullets::Multiple(vec) => for fires in vec { handle_fires_bullets(entities, updater, fires, total_time, player_positions, pos, difficulty); }
     }
 }
 
@@ -66,14 +72,48 @@ pub struct Collisions;
 
 impl<'a> System<'a> for Collisions {
     type SystemData = (
-        Entities<'a>, Read<'a, LazyUpdate>, Read<'a, GameTime>,
+        Entities<'a>, Read<'a, LazyUpdate>, Read<'a, GameTime>, Read<'a, Settings>, Write<'a, ActiveSpellCard>,
         ReadStorage<'a, Position>, ReadStorage<'a, Friendly>, ReadStorage<'a, Enemy>, ReadStorage<'a, Hitbox>, ReadStorage<'a, FrozenUntil>,
-        WriteStorage<'a, Health>, WriteStorage<'a, Invulnerability>,
+        ReadStorage<'a, Player>, ReadStorage<'a, Boss>, WriteStorage<'a, Health>, WriteStorage<'a, Invulnerability>, WriteStorage<'a, PendingHit>,
+        ReadStorage<'a, DropTable>, ReadStorage<'a, ScoreValue>, ReadStorage<'a, CollidesWithBomb>, WriteStorage<'a, Grazed>, Write<'a, GameEvents>,
+        Read<'a, Practice>, ReadStorage<'a, ShotBy>,
     );
 
-    fn run(&mut self, (entities, updater, time, pos, friendly, enemy, hitbox, frozen, mut health, mut invul): Self::SystemData) {
+    fn run(&mut self, (entities, updater, time, settings, mut spell_card, pos, friendly, enemy, hitbox, frozen, player, boss, mut health, mut invul, mut pending_hit, drops, score_value, collides, mut grazed, mut events, practice, shot_by): Self::SystemData) {
         let mut rng = rand::thread_rng();
-        
+
+        // Apply the hits that weren't cancelled by a bomb in time
+        let expired: Vec<Entity> = (&entities, &pending_hit).join()
+            .filter(|(_, hit)| hit.expires <= time.total_time)
+            .map(|(entity, _)| entity)
+            .collect();
+
+        for entity in expired {
+            pending_hit.remove(entity);
+            // Practice has infinite lives, but the hit still counts against spell cards
+            if !practice.active {
+                apply_damage(entity, &entities, &mut health, 1);
+            }
+            spell_card.fail();
+            events.single_write(GameEvent::PlayerHit { player: entity });
+        }
+
+        let mut newly_grazed = Vec::new();
+
+        // Enemy bullets that pass close to a player
+        for (player_entity, player_pos, _) in (&entities, &pos, &player).join() {
+            for (bullet, bullet_pos, _, _) in (&entities, &pos, &collides, !&grazed).join() {
+                if player_pos.0.distance2(bullet_pos.0) <= GRAZE_RADIUS.powi(2) {
+                    newly_grazed.push(bullet);
+                    events.single_write(GameEvent::BulletGrazed { player: player_entity });
+                }
+            }
+        }
+
+        for bullet in newly_grazed {
+            grazed.insert(bullet, Grazed).unwrap();
+        }
+
         (&entities, &pos, &hitbox, &friendly).join()
             .flat_map(|friendly| {
                 (&entities, &pos, &hitbox, !&frozen, &enemy).join()
@@ -82,25 +122,46 @@ impl<'a> System<'a> for Collisions {
             .for_each(|((f_entity, f_pos, f_hitbox, _), (e_entity, e_pos, e_hitbox, _, _))| {
                 if let Some(mut hit_pos) = is_touching(f_pos.0, f_hitbox.0, e_pos.0, e_hitbox.0) {
 
-                    let (player_triggered_invul, _) = damage_entity(f_entity, &entities, &mut health, &mut invul, time.total_time);
+                    let player_triggered_invul = if player.contains(f_entity) {
+                        hit_player(f_entity, &mut invul, &mut pending_hit, time.total_time, settings.death_bomb_window)
+                    } else {
+                        damage_entity(f_entity, &entities, &mut health, &mut invul, time.total_time, 1).0
+                    };
+
                     if player_triggered_invul {
-                        let (_, enemy_dead) = damage_entity(e_entity, &entities, &mut health, &mut invul, time.total_time);
+                        let boss_invulnerable = boss.get(e_entity).map(|boss| boss.invulnerable(time.total_time)).unwrap_or(false);
+                        let damage = match (boss.get(e_entity), health.get(e_entity)) {
+                            (Some(boss), Some(health)) => boss.clamp_damage(health.0, 1),
+                            _ => 1,
+                        };
+                        let enemy_dead = !boss_invulnerable && damage_entity(e_entity, &entities, &mut health, &mut invul, time.total_time, damage).1;
 
                         hit_pos.x += rng.gen_range(-5.0, 5.0);
                         hit_pos.y += rng.gen_range(-5.0, 5.0);
             
                         build_explosion(&updater, &entities, hit_pos, time.total_time);
 
-                        if enemy_dead && rng.gen_range(0.0, 1.0) > 0.6 {
-                            let (value, image) = if rng.gen_range(0.0, 1.0) > 0.9 { (5, GraphicsImage::BigOrb) } else { (1, GraphicsImage::Orb) };
-                            updater.create_entity(&entities)
-                                .with(Position(hit_pos))
-                                .with(PowerOrb(value))
-                                .with(Falling { speed: 0.0, down: true })
-                                .with(Image::from(image))
-                                .with(Hitbox(Vector2::new(50.0, 50.0)))
-                                .with(DieOffscreen)
-                                .build();
+                        if enemy_dead {
+                            // Either a player rammed the enemy or it was one of their bullets
+                            let killer = if player.contains(f_entity) {
+                                Some(f_entity)
+                            } else {
+                                shot_by.get(f_entity).map(|shot_by| shot_by.0)
+                            };
+
+                            events.single_write(GameEvent::EnemyKilled {
+                                enemy: e_entity,
+                                position: e_pos.0,
+                                score: score_value.get(e_entity).map(|value| value.0).unwrap_or(0),
+                                boss: boss.contains(e_entity),
+                                killer,
+                            });
+
+                            if let Some(drops) = drops.get(e_entity) {
+                                for item in drops.roll(&mut rng) {
+                                    build_item(&entities, &updater, hit_pos, item);
+                                }
+                            }
                         }
                     }
                 }
@@ -108,43 +169,130 @@ impl<'a> System<'a> for Collisions {
     }
 }
 
-fn damage_entity(entity: Entity, entities: &Entities, health: &mut WriteStorage<Health>, invul: &mut WriteStorage<Invulnerability>, time: f32) -> (bool, bool) {
+fn damage_entity(entity: Entity, entities: &Entities, health: &mut WriteStorage<Health>, invul: &mut WriteStorage<Invulnerability>, time: f32, damage: u32) -> (bool, bool) {
     let (mut triggered_invul, mut dead) = (false, false);
     
-    if let Some(health) = health.get_mut(entity) {
+    if health.contains(entity) {
         triggered_invul = invul.get_mut(entity).map(|invul| invul.can_damage(time)).unwrap_or(true);
 
         if triggered_invul {
-            health.0 = health.0.saturating_sub(1);
-
-            if health.0 == 0 {
-                dead = true;
-                entities.delete(entity).unwrap();
-            }
+            dead = apply_damage(entity, entities, health, damage);
         }
     }
 
     (triggered_invul, dead)
 }
 
+// Players aren't damaged straight away, so that they have a chance to cancel the hit by bombing.
+fn hit_player(entity: Entity, invul: &mut WriteStorage<Invulnerability>, pending_hit: &mut WriteStorage<PendingHit>, time: f32, window: f32) -> bool {
+    let triggered_invul = invul.get_mut(entity).map(|invul| invul.can_damage(time)).unwrap_or(true);
+
+    if triggered_invul {
+        pending_hit.insert(entity, PendingHit { expires: time + window }).unwrap();
+    }
+
+    triggered_invul
+}
+
+fn apply_damage(entity: Entity, entities: &Entities, health: &mut WriteStorage<Health>, damage: u32) -> bool {
+    match health.get_mut(entity) {
+        Some(health) => {
+            health.0 = health.0.saturating_sub(damage);
+
+            let dead = health.0 == 0;
+            if dead {
+                entities.delete(entity).unwrap();
+            }
+            dead
+        },
+        None => false
+    }
+}
+
 pub struct ExpandBombs;
 
 impl<'a> System<'a> for ExpandBombs {
-    type SystemData = (Entities<'a>, Read<'a, specs::world::LazyUpdate>, Read<'a, GameTime>, WriteStorage<'a, Circle>, ReadStorage<'a, Position>, ReadStorage<'a, CollidesWithBomb>);
+    type SystemData = (
+        Entities<'a>, Read<'a, LazyUpdate>, Read<'a, GameTime>,
+        WriteStorage<'a, Bomb>, WriteStorage<'a, Position>, ReadStorage<'a, CollidesWithBomb>,
+        ReadStorage<'a, Enemy>, ReadStorage<'a, Boss>, ReadStorage<'a, FrozenUntil>,
+        WriteStorage<'a, Health>, ReadStorage<'a, ScoreValue>, Write<'a, GameEvents>,
+    );
 
-    fn run(&mut self, (entities, updater, time, mut circle, position, collides): Self::SystemData) {
-        for (entity, mut circle, circle_pos) in (&entities, &mut circle, &position).join() {
-            circle.radius += 8.0;
-            
-            if circle.radius.powi(2) >= Vector2::new(WIDTH, HEIGHT).magnitude2() {
-                entities.delete(entity).unwrap();
+    fn run(&mut self, (entities, updater, time, mut bomb, mut position, collides, enemy, boss, frozen, mut health, score_value, mut events): Self::SystemData) {
+        for (bomb_entity, bomb) in (&entities, &mut bomb).join() {
+            if bomb.finished(time.total_time) {
+                entities.delete(bomb_entity).unwrap();
+                continue;
+            }
+
+            let mut bomb_pos = match position.get(bomb_entity) {
+                Some(pos) => pos.0,
+                None => continue
+            };
+
+            if bomb.follows_owner() {
+                if let Some(owner_pos) = position.get(bomb.owner) {
+                    bomb_pos = owner_pos.0;
+                }
+            }
+
+            match &mut bomb.kind {
+                BombKind::Circle { radius } => *radius += 8.0,
+                BombKind::Beams { rotation } => *rotation += Bomb::BEAM_ROTATION_SPEED,
+                BombKind::Spirit { velocity } => {
+                    let closest = (&position, &enemy, !&collides, !&frozen).join()
+                        .map(|(pos, ..)| pos.0)
+                        .min_by(|a, b| a.distance2(bomb_pos).partial_cmp(&b.distance2(bomb_pos)).unwrap());
+
+                    // Steer towards the closest enemy
+                    if let Some(closest) = closest {
+                        if closest != bomb_pos {
+                            *velocity = (*velocity + (closest - bomb_pos).normalize_to(0.5)).normalize_to(Bomb::SPIRIT_SPEED);
+                        }
+                    }
+
+                    bomb_pos += *velocity;
+                },
+                BombKind::Flash | BombKind::Shield => {}
             }
 
+            position.insert(bomb_entity, Position(bomb_pos)).unwrap();
+
             for (entity, pos, _) in (&entities, &position, &collides).join() {
-                if pos.0.distance2(circle_pos.0) <= circle.radius.powi(2) {
+                if bomb.covers(bomb_pos, pos.0) {
                     entities.delete(entity).unwrap();
 
                     build_explosion(&updater, &entities, pos.0, time.total_time);
+
+                    events.single_write(GameEvent::BulletConverted { player: bomb.owner, score: bomb.ty.bullet_score() });
+                }
+            }
+
+            if bomb.damage_cooldown.is_ready(time.total_time) {
+                for (entity, pos, health, _, _, _) in (&entities, &position, &mut health, &enemy, !&collides, !&frozen).join() {
+                    let boss_invulnerable = boss.get(entity).map(|boss| boss.invulnerable(time.total_time)).unwrap_or(false);
+
+                    if bomb.covers(bomb_pos, pos.0) && !boss_invulnerable {
+                        let damage = match boss.get(entity) {
+                            Some(boss) => boss.clamp_damage(health.0, bomb.ty.boss_damage()),
+                            None => bomb.ty.damage(),
+                        };
+                        health.0 = health.0.saturating_sub(damage);
+
+                        if health.0 == 0 {
+                            entities.delete(entity).unwrap();
+                            build_explosion(&updater, &entities, pos.0, time.total_time);
+
+                            events.single_write(GameEvent::EnemyKilled {
+                                enemy: entity,
+                                position: pos.0,
+                                score: score_value.get(entity).map(|value| value.0).unwrap_or(0),
+                                boss: boss.contains(entity),
+                                killer: Some(bomb.owner),
+                            });
+                        }
+                    }
                 }
             }
         }
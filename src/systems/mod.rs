@@ -1,32 +1,45 @@
 use specs::prelude::*;
+use specs::shrev::ReaderId;
 
 use crate::components::*;
 use crate::resources::*;
+use crate::archetypes::Archetypes;
+use crate::stages::StageSetup;
+use crate::audio::{Audio, Sound};
 
-use cgmath::{Vector2, InnerSpace, MetricSpace};
+use cgmath::{Vector2, MetricSpace};
+use rand::Rng;
 
 use crate::{WIDTH, HEIGHT};
 use crate::graphics::Image as GraphicsImage;
 
 const PLAYER_SPEED: f32 = 250.0 / 60.0;
 const PLAYER_BULLET_SPEED: f32 = 1000.0 / 60.0;
+const POINT_OF_COLLECTION: f32 = HEIGHT / 4.0;
+const GRAZE_SCORE: u32 = 50;
+const ITEM_ATTRACT_RADIUS: f32 = 50.0;
+const ITEM_ATTRACT_SPEED: f32 = 8.0;
+const MAX_LIVES: u32 = 8;
 
 mod rendering;
 mod bullets;
+mod bot;
 
 pub use rendering::*;
 pub use bullets::*;
+pub use bot::*;
 
 pub struct MoveEntities;
 
 impl<'a> System<'a> for MoveEntities {
     type SystemData = (
-        WriteStorage<'a, Position>, ReadStorage<'a, FrozenUntil>, Read<'a, GameTime>,
+        Entities<'a>, WriteStorage<'a, Position>, ReadStorage<'a, FrozenUntil>, Read<'a, GameTime>,
         WriteStorage<'a, FiringMove>, ReadStorage<'a, MoveTowards>, ReadStorage<'a, Velocity>, WriteStorage<'a, Falling>, WriteStorage<'a, FollowCurve>,
+        Write<'a, GameEvents>,
     );
 
-    fn run(&mut self, (mut pos, frozen, game_time, firing_move, move_towards, vel, mut falling, mut curve): Self::SystemData) {
-        for (mut pos, vel, falling, curve, move_towards, firing_move, _) in (&mut pos, vel.maybe(), (&mut falling).maybe(), (&mut curve).maybe(), move_towards.maybe(), firing_move.maybe(), !&frozen).join() {
+    fn run(&mut self, (entities, mut pos, frozen, game_time, firing_move, move_towards, vel, mut falling, mut curve, mut events): Self::SystemData) {
+        for (entity, mut pos, vel, falling, curve, move_towards, firing_move, _) in (&entities, &mut pos, vel.maybe(), (&mut falling).maybe(), (&mut curve).maybe(), move_towards.maybe(), firing_move.maybe(), !&frozen).join() {
             if let Some(vel) = vel {
                 pos.0 += vel.0;
             }
@@ -42,15 +55,15 @@ impl<'a> System<'a> for MoveEntities {
             }
 
             if let Some(curve) = curve {
-                pos.0 = curve.step(pos.0);
+                pos.0 = curve.step();
+
+                if curve.take_end() {
+                    events.single_write(GameEvent::PathEnded { entity, on_end: curve.on_end });
+                }
             }
 
             if let Some(move_towards) = move_towards {
-                if pos.0.distance2(move_towards.position) > move_towards.speed.powi(2) {
-                    pos.0 += (move_towards.position - pos.0).normalize_to(move_towards.speed);
-                } else {
-                    pos.0 = move_towards.position;
-                }
+                pos.0 = step_towards(pos.0, move_towards.position, move_towards.speed).0;
             }
 
             if let Some(firing_move) = firing_move {
@@ -64,17 +77,121 @@ impl<'a> System<'a> for MoveEntities {
     }
 }
 
+// Despawns entities or switches them to chasing a player when their path ends.
+#[derive(Default)]
+pub struct EndPaths {
+    reader: Option<ReaderId<GameEvent>>,
+}
+
+impl<'a> System<'a> for EndPaths {
+    type SystemData = (Entities<'a>, Read<'a, GameEvents>, WriteStorage<'a, FollowCurve>, WriteStorage<'a, TargetPlayer>);
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<GameEvents>().register_reader());
+    }
+
+    fn run(&mut self, (entities, events, mut curve, mut target): Self::SystemData) {
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            match *event {
+                GameEvent::PathEnded { entity, on_end: PathEnd::Despawn } => entities.delete(entity).unwrap(),
+                GameEvent::PathEnded { entity, on_end: PathEnd::TargetPlayer(speed) } => {
+                    curve.remove(entity);
+                    target.insert(entity, TargetPlayer(speed)).unwrap();
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+pub struct SpawnChildren;
+
+impl<'a> System<'a> for SpawnChildren {
+    type SystemData = (
+        Entities<'a>, Read<'a, LazyUpdate>, Read<'a, GameTime>, Read<'a, Archetypes>,
+        ReadStorage<'a, Position>, ReadStorage<'a, Health>, ReadStorage<'a, BeenOnscreen>, ReadStorage<'a, FrozenUntil>,
+        WriteStorage<'a, Spawner>, ReadStorage<'a, SpawnedBy>, Read<'a, Difficulty>,
+    );
+
+    fn run(&mut self, (entities, updater, time, archetypes, position, health, onscreen, frozen, mut spawner, spawned_by, difficulty): Self::SystemData) {
+        for (entity, pos, health, spawner, _, _) in (&entities, &position, &health, &mut spawner, &onscreen, !&frozen).join() {
+            let children = (&spawned_by).join().filter(|spawned_by| spawned_by.0 == entity).count();
+
+            // Spawners killed this frame are still around with no health
+            let to_spawn = if health.0 == 0 {
+                if spawner.release_on_death { spawner.max_children.saturating_sub(children) } else { 0 }
+            } else if children < spawner.max_children && spawner.cooldown.is_ready(time.total_time) {
+                1
+            } else {
+                0
+            };
+
+            let archetype = archetypes.get(&spawner.child);
+
+            for i in 0 .. to_spawn {
+                // Spread out children that are released together
+                let rotation = i as f32 / to_spawn as f32 * std::f32::consts::PI * 2.0;
+                let offset = if to_spawn > 1 { Vector2::new(rotation.cos(), rotation.sin()) * 20.0 } else { Vector2::new(0.0, 0.0) };
+
+                archetype.spawn(updater.create_entity(&entities), pos.0 + offset, time.total_time, *difficulty)
+                    .with(SpawnedBy(entity))
+                    .build();
+            }
+        }
+    }
+}
+
 pub struct MoveBosses;
 
 impl<'a> System<'a> for MoveBosses {
-    type SystemData = (Entities<'a>, ReadStorage<'a, Position>, WriteStorage<'a, Boss>, WriteStorage<'a, MoveTowards>, WriteStorage<'a, FiresBullets>);
+    type SystemData = (
+        Entities<'a>, Read<'a, LazyUpdate>, Read<'a, GameTime>, Read<'a, PlayerPositions>, Write<'a, ActiveSpellCard>, Write<'a, CaptureHistory>,
+        WriteStorage<'a, Position>, ReadStorage<'a, Health>, ReadStorage<'a, FrozenUntil>, ReadStorage<'a, CollidesWithBomb>, ReadStorage<'a, SpellCardName>,
+        WriteStorage<'a, Boss>, WriteStorage<'a, FiresBullets>, Write<'a, GameEvents>, Read<'a, Difficulty>, Read<'a, Practice>,
+    );
 
-    fn run(&mut self, (entities, pos, mut boss, mut move_towards, mut fires): Self::SystemData) {
-        for (entity, pos, mut boss) in (&entities, &pos, &mut boss).join() {
-            let target_position = boss.current_move().position;
-            move_towards.insert(entity, MoveTowards { position: target_position, speed: 10.0 / 3.0}).unwrap();
+    fn run(&mut self, (entities, updater, time, player_positions, mut spell_card, mut history, mut position, health, frozen, collides, names, mut boss, mut fires, mut events, difficulty, practice): Self::SystemData) {
+        let mut clear_bullets = false;
+
+        for (entity, pos, health, mut boss, _) in (&entities, &mut position, &health, &mut boss, !&frozen).join() {
+            if boss.phase_timer == 0.0 {
+                // Practice attempts don't count towards the capture history
+                if let (Some(card), false) = (boss.phase().spell_card, practice.active) {
+                    history.record_attempt(card, *difficulty);
+                }
 
-            if pos.0 == target_position {
+                start_spell_card(&boss, &mut spell_card, &history, &entities, &updater, time.total_time, *difficulty);
+            }
+
+            boss.phase_timer += 1.0 / 60.0;
+
+            if boss.should_change_phase(health.0) {
+                let beaten = health.0 <= boss.phase().health_threshold;
+                let spell_card_captured = finish_spell_card(&mut spell_card, &mut history, &mut events, &entities, &names, beaten, practice.active);
+
+                boss.change_phase(time.total_time);
+                events.single_write(GameEvent::BossPhaseChanged { phase: boss.current_phase, spell_card_captured });
+                fires.remove(entity);
+                clear_bullets = true;
+            } else if boss.timed_out() && boss.phases_remaining() == 0 {
+                // Surviving the final attack ends the fight
+                finish_spell_card(&mut spell_card, &mut history, &mut events, &entities, &names, false, practice.active);
+                entities.delete(entity).unwrap();
+                build_explosion(&updater, &entities, pos.0, time.total_time);
+                continue;
+            }
+
+            // Track the closest player
+            let player_x = player_positions.0.iter()
+                .map(|player| player.x)
+                .min_by(|a, b| (a - pos.0.x).abs().partial_cmp(&(b - pos.0.x).abs()).unwrap())
+                .unwrap_or(pos.0.x);
+
+            let (new_pos, finished_moving) = boss.step_movement(pos.0, player_x);
+            pos.0 = new_pos;
+
+            if finished_moving || boss.current_move().fire_while_moving {
                 if let specs::storage::StorageEntry::Vacant(slot) = fires.entry(entity).unwrap() {
                     slot.insert(boss.current_move().fires.clone());
                 }
@@ -83,15 +200,71 @@ impl<'a> System<'a> for MoveBosses {
 
                 if boss.move_timer >= boss.current_move().duration {
                     fires.remove(entity);
-
-                    boss.move_timer = 0.0;
-                    boss.current_move = (boss.current_move + 1) % boss.moves.len();
+                    boss.next_move();
                 }
             }
         }
+
+        // Clear the bullets from the previous phase
+        if clear_bullets {
+            for (bullet, bullet_pos, _) in (&entities, &position, &collides).join() {
+                entities.delete(bullet).unwrap();
+                build_explosion(&updater, &entities, bullet_pos.0, time.total_time);
+            }
+        }
     }
 }
 
+fn start_spell_card(boss: &Boss, spell_card: &mut ActiveSpellCard, history: &CaptureHistory, entities: &Entities, updater: &LazyUpdate, time: f32, difficulty: Difficulty) {
+    let phase = boss.phase();
+
+    spell_card.0 = phase.spell_card.map(|card| {
+        let (captures, attempts) = history.get(card, difficulty)
+            .map(|record| (record.captures, record.attempts))
+            .unwrap_or((0, 0));
+
+        updater.create_entity(entities)
+            .with(Text {
+                text: format!("{}\nHistory: {}/{}", card.name, captures, attempts),
+                font: 1,
+                layout: wgpu_glyph::Layout::default().h_align(wgpu_glyph::HorizontalAlign::Right)
+            })
+            .with(Position(Vector2::new(WIDTH - 10.0, 30.0)))
+            .with(SpellCardName)
+            .build();
+
+        SpellCardAttempt {
+            card,
+            difficulty,
+            started: time,
+            failed: false,
+        }
+    });
+}
+
+fn finish_spell_card(
+    spell_card: &mut ActiveSpellCard, history: &mut CaptureHistory, events: &mut GameEvents,
+    entities: &Entities, names: &ReadStorage<SpellCardName>, beaten: bool, practice: bool,
+) -> bool {
+    let mut captured = false;
+
+    if let Some(attempt) = spell_card.0.take() {
+        if beaten && !attempt.failed {
+            if !practice {
+                history.record_capture(attempt.card, attempt.difficulty);
+            }
+            captured = true;
+            events.single_write(GameEvent::SpellCardCaptured { bonus: attempt.card.bonus });
+        }
+    }
+
+    for (entity, _) in (entities, names).join() {
+        entities.delete(entity).unwrap();
+    }
+
+    captured
+}
+
 fn min(a: f32, b: f32) -> f32 {
     if a < b {
         a
@@ -116,8 +289,8 @@ impl<'a> System<'a> for TogglePaused {
     fn run(&mut self, (mut ctrl_state, mut mode): Self::SystemData) {
         if ctrl_state.pause.pressed {
             *mode = match *mode {
-                Mode::Playing { stage, state, multiplayer } => Mode::Paused { selected: 0, stage, state, multiplayer },
-                Mode::Paused { stage, state, multiplayer, .. } => Mode::Playing { stage, state, multiplayer },
+                Mode::Playing { stage, state, multiplayer, bomb } => Mode::Paused { selected: 0, stage, state, multiplayer, bomb },
+                Mode::Paused { stage, state, multiplayer, bomb, .. } => Mode::Playing { stage, state, multiplayer, bomb },
                 _ => *mode
             };
             ctrl_state.pause.pressed = false;
@@ -128,19 +301,24 @@ impl<'a> System<'a> for TogglePaused {
 pub struct ControlMenu;
 
 impl<'a> System<'a> for ControlMenu {
-    type SystemData = (Write<'a, ControlsState>, Write<'a, Mode>, Entities<'a>, Read<'a, LazyUpdate>, Write<'a, GameTime>);
+    type SystemData = (
+        Write<'a, ControlsState>, Write<'a, Mode>, Entities<'a>, Read<'a, LazyUpdate>, Write<'a, GameTime>, Read<'a, Archetypes>, Write<'a, Audio>,
+        Write<'a, Settings>, Write<'a, Difficulty>, Write<'a, Practice>, Write<'a, BossRush>, Read<'a, BossRushRecords>, Write<'a, ActiveSpellCard>,
+    );
 
-    fn run(&mut self, (mut ctrl_state, mut mode, entities, updater, mut time): Self::SystemData) {
-        if let Some(mut menu) = mode.as_menu(&ctrl_state) {
+    fn run(&mut self, (mut ctrl_state, mut mode, entities, updater, mut time, archetypes, mut audio, mut settings, mut current_difficulty, mut practice, mut boss_rush, records, mut spell_card): Self::SystemData) {
+        if let Some(mut menu) = mode.as_menu(&ctrl_state, &settings, &records) {
             let player_ctrl_state = ctrl_state.get_mut(Player::Single);
 
             if player_ctrl_state.down.pressed {
                 menu.rotate_down();
+                audio.play(Sound::MenuMove);
                 player_ctrl_state.down.pressed = false;
             }
 
             if player_ctrl_state.up.pressed {
                 menu.rotate_up();
+                audio.play(Sound::MenuMove);
                 player_ctrl_state.up.pressed = false;
             }
 
@@ -148,56 +326,108 @@ impl<'a> System<'a> for ControlMenu {
 
             if player_ctrl_state.fire.pressed {
                 match *mode {
-                    Mode::Paused { selected, stage, state, multiplayer } => {
+                    Mode::Paused { selected, stage, state, multiplayer, bomb } => {
                         *mode = match selected {
-                            0 => Mode::Playing { stage, state, multiplayer } ,
-                            1 => Mode::MainMenu { selected: 0 },
+                            0 => Mode::Playing { stage, state, multiplayer, bomb },
+                            1 => {
+                                // Quitting mid spell card shouldn't leave the attempt running
+                                *spell_card = ActiveSpellCard::default();
+                                Mode::MainMenu { selected: 0 }
+                            },
                             _ => unreachable!()
                         }
                     },
                     Mode::MainMenu { selected } => {
                         *mode = match selected {
-                            0 => Mode::Stages { selected: 0, multiplayer: false },
-                            1 => Mode::Controls { selected: 0 },
+                            0 => Mode::Stages { selected: 0, multiplayer: false, bomb: BombType::Circle, difficulty: *current_difficulty },
+                            1 => Mode::Practice { selected: 0, stage: Stage::One, start: StageStart::Time(0.0), slow_motion: false },
+                            2 => Mode::Controls { selected: 0 },
+                            3 => Mode::Settings { selected: 0 },
                             #[cfg(feature = "native")]
-                            2 => Mode::Quit,
+                            4 => Mode::Quit,
                             _ => unreachable!()
                         };
                     },
-                    Mode::Stages { selected, multiplayer } => {
+                    Mode::Stages { selected, multiplayer, bomb, difficulty } => {
+                        *mode = match selected {
+                            0 | 1 => {
+                                let stage = if selected == 0 { Stage::One } else { Stage::Two };
+                                let setup = StageSetup { multiplayer, bomb, difficulty, start: StageStart::Time(0.0) };
+                                *current_difficulty = difficulty;
+                                *practice = Practice::default();
+                                *spell_card = ActiveSpellCard::default();
+                                audio.restart_music();
+                                *boss_rush = BossRush::default();
+                                crate::stages::start(stage, &entities, &updater, &archetypes, setup, &mut time.total_time);
+                                Mode::Playing { stage, multiplayer, bomb, state: PlayingState::Playing }
+                            },
+                            2 => {
+                                let setup = StageSetup { multiplayer, bomb, difficulty, start: StageStart::Time(0.0) };
+                                *current_difficulty = difficulty;
+                                *practice = Practice::default();
+                                *spell_card = ActiveSpellCard::default();
+                                audio.restart_music();
+                                *boss_rush = BossRush { active: true, boss: 0 };
+                                crate::stages::boss_rush(&entities, &updater, &archetypes, setup, &mut time.total_time);
+                                Mode::Playing { stage: crate::stages::BOSS_RUSH[0], multiplayer, bomb, state: PlayingState::Playing }
+                            },
+                            3 => Mode::Stages { selected, multiplayer: !multiplayer, bomb, difficulty },
+                            4 => Mode::Stages { selected, multiplayer, bomb: bomb.next(), difficulty },
+                            5 => Mode::Stages { selected, multiplayer, bomb, difficulty: difficulty.next() },
+                            6 => Mode::MainMenu { selected: 0 },
+                            _ => unreachable!()
+                        }
+                    },
+                    Mode::Practice { selected, stage, start, slow_motion } => {
                         *mode = match selected {
                             0 => {
-                                crate::stages::stage_one(&entities, &updater, multiplayer, &mut time.total_time);
-                                Mode::Playing { stage: Stage::One, multiplayer, state: PlayingState::Playing }
+                                let stage = if stage == Stage::One { Stage::Two } else { Stage::One };
+                                Mode::Practice { selected, stage, start: StageStart::Time(0.0), slow_motion }
                             },
-                            1 => {
-                                crate::stages::stage_two(&entities, &updater, multiplayer, &mut time.total_time);
-                                Mode::Playing { stage: Stage::Two, multiplayer, state: PlayingState::Playing }
+                            1 => Mode::Practice { selected, stage, start: crate::stages::next_start(stage, start), slow_motion },
+                            2 => Mode::Practice { selected, stage, start, slow_motion: !slow_motion },
+                            3 => {
+                                let setup = StageSetup { multiplayer: false, bomb: BombType::Circle, difficulty: *current_difficulty, start };
+                                *practice = Practice { active: true, slow_motion };
+                                *spell_card = ActiveSpellCard::default();
+                                audio.restart_music();
+                                *boss_rush = BossRush::default();
+                                crate::stages::start(stage, &entities, &updater, &archetypes, setup, &mut time.total_time);
+                                Mode::Playing { stage, multiplayer: false, bomb: BombType::Circle, state: PlayingState::Playing }
                             },
-                            2 => Mode::Stages { selected, multiplayer: !multiplayer },
-                            3 => Mode::MainMenu { selected: 0 },
+                            4 => Mode::MainMenu { selected: 1 },
                             _ => unreachable!()
                         }
                     },
                     Mode::Controls { selected } => {
                         if selected == last_item {
-                            *mode = Mode::MainMenu { selected: 1 };
+                            *mode = Mode::MainMenu { selected: 2 };
+                        }
+                    },
+                    Mode::Settings { selected } => {
+                        if !settings.select(selected) {
+                            settings.save();
+                            *mode = Mode::MainMenu { selected: 3 };
                         }
                     },
-                    Mode::StageComplete { stage, selected, multiplayer } => {
+                    Mode::StageComplete { stage, selected, multiplayer, bomb, score, high_score } => {
                         *mode = match selected {
                             0 => match stage {
                                 Stage::One => {
-                                    crate::stages::stage_one(&entities, &updater, multiplayer, &mut time.total_time);
-                                    Mode::Playing { stage: Stage::Two, multiplayer, state: PlayingState::Playing }
+                                    let setup = StageSetup { multiplayer, bomb, difficulty: *current_difficulty, start: StageStart::Time(0.0) };
+                                    *practice = Practice::default();
+                                    *spell_card = ActiveSpellCard::default();
+                                    audio.restart_music();
+                                    crate::stages::start(Stage::Two, &entities, &updater, &archetypes, setup, &mut time.total_time);
+                                    Mode::Playing { stage: Stage::Two, multiplayer, bomb, state: PlayingState::Playing }
                                 },
-                                Stage::Two => Mode::StageComplete { stage, selected, multiplayer }
+                                Stage::Two => Mode::StageComplete { stage, selected, multiplayer, bomb, score, high_score }
                             },
                             1 => Mode::MainMenu { selected: 0 },
                             _ => unreachable!()
                         }
                     },
-                    Mode::StageLost { .. } => *mode = Mode::MainMenu { selected: 0 },
+                    Mode::StageLost { .. } | Mode::BossRushComplete { .. } => *mode = Mode::MainMenu { selected: 0 },
                     Mode::Playing { .. } | Mode::Quit => {}
                 }
 
@@ -211,14 +441,17 @@ pub struct Control;
 
 impl<'a> System<'a> for Control {
     type SystemData = (
-        Entities<'a>, Read<'a, ControlsState>, Read<'a, GameTime>, Read<'a, LazyUpdate>,
+        Entities<'a>, Read<'a, ControlsState>, Read<'a, Settings>, Read<'a, GameTime>, Read<'a, LazyUpdate>,
         ReadStorage<'a, Player>, WriteStorage<'a, Position>, WriteStorage<'a, Cooldown>, WriteStorage<'a, PowerBar>,
+        ReadStorage<'a, BombType>, WriteStorage<'a, Invulnerability>, ReadStorage<'a, Bomb>, WriteStorage<'a, PendingHit>,
+        Write<'a, ActiveSpellCard>, Write<'a, GameEvents>,
     );
 
-    fn run(&mut self, (entities, ctrl_state, time, updater, player, mut position, mut cooldown, mut bar): Self::SystemData) {
-        for (player, mut pos, cooldown, bar) in (&player, &mut position, &mut cooldown, &mut bar).join() {
+    fn run(&mut self, (entities, ctrl_state, settings, time, updater, player, mut position, mut cooldown, mut bar, bomb_type, mut invul, bomb, mut pending_hit, mut spell_card, mut events): Self::SystemData) {
+        for (entity, player, mut pos, cooldown, bar, bomb_type, invul) in (&entities, &player, &mut position, &mut cooldown, &mut bar, &bomb_type, &mut invul).join() {
             let player_ctrl_state = ctrl_state.get(*player);
-            let speed = if player_ctrl_state.slow_movement.pressed { PLAYER_SPEED / 2.0 } else { PLAYER_SPEED };
+            let focused = player_ctrl_state.slow_movement.pressed;
+            let speed = if focused { PLAYER_SPEED / 2.0 } else { PLAYER_SPEED };
 
             if player_ctrl_state.left.pressed {
                 pos.0.x = max(pos.0.x - speed, 0.0);
@@ -237,16 +470,39 @@ impl<'a> System<'a> for Control {
             }
 
             if player_ctrl_state.fire.pressed && cooldown.is_ready(time.total_time) {
-                for direction in &[-0.2_f32, -0.1, 0.0, 0.1, 0.2] {
-                    build_bullet(&entities, &updater, pos.0, Image::from(GraphicsImage::PlayerBullet), Vector2::new(direction.sin(), -direction.cos()) * PLAYER_BULLET_SPEED, false, None);
+                // Focusing narrows the spread of the shot
+                let directions = if focused && settings.focused_shot {
+                    &[-0.05_f32, -0.025, 0.0, 0.025, 0.05]
+                } else {
+                    &[-0.2_f32, -0.1, 0.0, 0.1, 0.2]
+                };
+
+                events.single_write(GameEvent::ShotFired { player: entity });
+
+                for direction in directions {
+                    build_bullet(&entities, &updater, pos.0, Image::from(GraphicsImage::PlayerBullet), Vector2::new(direction.sin(), -direction.cos()) * PLAYER_BULLET_SPEED, Shooter::Player(entity), None);
                 }
             }
 
-            if player_ctrl_state.bomb.pressed && bar.empty() {
-                updater.create_entity(&entities)
-                    .with(Position(pos.0))
-                    .with(Circle { radius: 0.0 })
-                    .build();
+            let bombing = (&bomb).join().any(|bomb| bomb.owner == entity);
+            let hit_pending = pending_hit.contains(entity);
+            let cost = if hit_pending { PowerBar::DEATH_BOMB_COST } else { PowerBar::BOMB_COST };
+
+            if player_ctrl_state.bomb.pressed && !bombing && bar.spend(cost) {
+                if hit_pending {
+                    pending_hit.remove(entity);
+                }
+
+                invul.grant(time.total_time, bomb_type.invulnerability());
+                spell_card.fail();
+                events.single_write(GameEvent::BombUsed { player: entity, bomb: *bomb_type, death_bomb: hit_pending });
+
+                for bomb in Bomb::spawn(*bomb_type, entity, time.total_time) {
+                    updater.create_entity(&entities)
+                        .with(Position(pos.0))
+                        .with(bomb)
+                        .build();
+                }
             }
         }
     }
@@ -326,6 +582,51 @@ impl<'a> System<'a> for KillOffscreen {
     }
 }
 
+// Rewards waves once every member has been killed.
+#[derive(Default)]
+pub struct CheckWaves {
+    reader: Option<ReaderId<GameEvent>>,
+}
+
+impl<'a> System<'a> for CheckWaves {
+    type SystemData = (
+        Entities<'a>, Read<'a, LazyUpdate>, Write<'a, GameEvents>,
+        WriteStorage<'a, Wave>, ReadStorage<'a, WaveMember>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<GameEvents>().register_reader());
+    }
+
+    fn run(&mut self, (entities, updater, mut events, mut wave, member): Self::SystemData) {
+        // Killed members are only removed when the world is maintained, so their wave can still be found
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            if let GameEvent::EnemyKilled { enemy, position, .. } = *event {
+                if let Some(wave) = member.get(enemy).and_then(|member| wave.get_mut(member.0)) {
+                    wave.killed += 1;
+                    wave.last_position = position;
+                }
+            }
+        }
+
+        for (wave_entity, wave) in (&entities, &mut wave).join() {
+            if (&entities, &member).join().any(|(_, member)| member.0 == wave_entity) {
+                continue;
+            }
+
+            entities.delete(wave_entity).unwrap();
+
+            if wave.killed >= wave.size {
+                match wave.reward {
+                    WaveReward::Score(bonus) => events.single_write(GameEvent::WaveBonus { score: bonus }),
+                    WaveReward::Item(item) => build_item(&entities, &updater, wave.last_position, item),
+                }
+            }
+        }
+    }
+}
+
 pub struct AddOnscreen;
 
 impl<'a> System<'a> for AddOnscreen {
@@ -349,20 +650,84 @@ fn is_onscreen(pos: &Position, image: Image) -> bool {
 pub struct CollectOrbs;
 
 impl<'a> System<'a> for CollectOrbs {
-    type SystemData = (Entities<'a>, ReadStorage<'a, PowerOrb>, ReadStorage<'a, Position>, ReadStorage<'a, Hitbox>, WriteStorage<'a, PowerBar>);
-
-    fn run(&mut self, (entities, orb, position, hitbox, mut power_bar): Self::SystemData) {
-        for (player_pos, player_hit, power_bar) in (&position, &hitbox, &mut power_bar).join() {
-            for (orb_entity, orb, orb_pos, orb_hit) in (&entities, &orb, &position, &hitbox).join() {
-                if is_touching(player_pos.0, player_hit.0, orb_pos.0, orb_hit.0).is_some() {
-                    entities.delete(orb_entity).unwrap();
-                    power_bar.add(orb.0);
+    type SystemData = (
+        Entities<'a>, ReadStorage<'a, Pickup>, ReadStorage<'a, Position>, ReadStorage<'a, Hitbox>,
+        WriteStorage<'a, PowerBar>, WriteStorage<'a, Health>, Write<'a, GameEvents>,
+    );
+
+    fn run(&mut self, (entities, item, position, hitbox, mut power_bar, mut health, mut events): Self::SystemData) {
+        for (player, player_pos, player_hit, power_bar, health) in (&entities, &position, &hitbox, &mut power_bar, &mut health).join() {
+            for (item_entity, item, item_pos, item_hit) in (&entities, &item, &position, &hitbox).join() {
+                if is_touching(player_pos.0, player_hit.0, item_pos.0, item_hit.0).is_some() {
+                    entities.delete(item_entity).unwrap();
+
+                    match *item {
+                        Pickup::Power(value) => power_bar.add(value),
+                        // Awarded by `AwardScore`
+                        Pickup::Points(_) => {},
+                        Pickup::Life => health.0 = (health.0 + 1).min(MAX_LIVES),
+                        Pickup::BombPiece => power_bar.add(PowerBar::BOMB_COST / 5),
+                    }
+
+                    events.single_write(GameEvent::ItemCollected { player, item: *item });
                 }
             }
         }
     }
 }
 
+// Points items are worth their full value above the point-of-collection line, down to half at the bottom of the screen.
+fn item_points(value: u32, y: f32) -> u32 {
+    let below = ((y - POINT_OF_COLLECTION) / (HEIGHT - POINT_OF_COLLECTION)).max(0.0).min(1.0);
+    (value as f32 * (1.0 - below / 2.0)) as u32
+}
+
+pub struct AttractItems;
+
+impl<'a> System<'a> for AttractItems {
+    type SystemData = (
+        Entities<'a>, ReadStorage<'a, Player>, ReadStorage<'a, Pickup>,
+        WriteStorage<'a, Position>, WriteStorage<'a, Falling>, WriteStorage<'a, Attracted>,
+    );
+
+    fn run(&mut self, (entities, player, item, mut position, mut falling, mut attracted): Self::SystemData) {
+        let players: Vec<(Entity, Vector2<f32>)> = (&entities, &player, &position).join()
+            .map(|(entity, _, pos)| (entity, pos.0))
+            .collect();
+
+        // Players above the point-of-collection line pull in every item on screen
+        let collecting = players.iter().find(|(_, pos)| pos.y < POINT_OF_COLLECTION);
+
+        let newly_attracted: Vec<(Entity, Entity)> = (&entities, &item, &position, !&attracted).join()
+            .filter_map(|(entity, _, pos, _)| {
+                collecting
+                    .or_else(|| players.iter().find(|(_, player_pos)| player_pos.distance2(pos.0) < ITEM_ATTRACT_RADIUS.powi(2)))
+                    .map(|(player, _)| (entity, *player))
+            })
+            .collect();
+
+        for (entity, player) in newly_attracted {
+            attracted.insert(entity, Attracted(player)).unwrap();
+            falling.remove(entity);
+        }
+
+        let mut released = Vec::new();
+
+        for (entity, pos, attracted) in (&entities, &mut position, &attracted).join() {
+            match players.iter().find(|(player, _)| *player == attracted.0) {
+                Some((_, player_pos)) => pos.0 = step_towards(pos.0, *player_pos, ITEM_ATTRACT_SPEED).0,
+                // The player died, so let the item fall again
+                None => released.push(entity),
+            }
+        }
+
+        for entity in released {
+            attracted.remove(entity);
+            falling.insert(entity, Falling { speed: 0.0, down: true }).unwrap();
+        }
+    }
+}
+
 fn is_touching(pos_a: Vector2<f32>, hit_a: Vector2<f32>, pos_b: Vector2<f32>, hit_b: Vector2<f32>) -> Option<Vector2<f32>> {
     if hit_a == Vector2::new(0.0, 0.0) && hit_b == Vector2::new(0.0, 0.0) {
         return None;
@@ -390,14 +755,21 @@ fn is_touching(pos_a: Vector2<f32>, hit_a: Vector2<f32>, pos_b: Vector2<f32>, hi
     }
 }
 
-fn build_bullet(entities: &Entities, updater: &LazyUpdate, pos: Vector2<f32>, image: Image, velocity: Vector2<f32>, enemy: bool, colour_bullets: Option<ColourBullets>) {
-    let mut builder = if enemy {
-        updater.create_entity(entities)
+// Who fired a bullet.
+#[derive(Clone, Copy)]
+enum Shooter {
+    Enemy,
+    Player(Entity),
+}
+
+fn build_bullet(entities: &Entities, updater: &LazyUpdate, pos: Vector2<f32>, image: Image, velocity: Vector2<f32>, shooter: Shooter, colour_bullets: Option<ColourBullets>) {
+    let mut builder = match shooter {
+        Shooter::Enemy => updater.create_entity(entities)
             .with(Enemy)
-            .with(CollidesWithBomb)
-    } else {
-        updater.create_entity(entities)
+            .with(CollidesWithBomb),
+        Shooter::Player(player) => updater.create_entity(entities)
             .with(Friendly)
+            .with(ShotBy(player)),
     }
         .with(Position(pos))
         .with(image)
@@ -414,32 +786,137 @@ fn build_bullet(entities: &Entities, updater: &LazyUpdate, pos: Vector2<f32>, im
     builder.build();
 }
 
+// Gives out points. All score changes go through here as events. Kills that can't be traced back to a
+// player aren't worth anything, while spell card and wave bonuses are given to every player.
+#[derive(Default)]
+pub struct AwardScore {
+    reader: Option<ReaderId<GameEvent>>,
+}
+
+impl<'a> System<'a> for AwardScore {
+    type SystemData = (Read<'a, GameEvents>, ReadStorage<'a, Player>, ReadStorage<'a, Position>, WriteStorage<'a, Score>);
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<GameEvents>().register_reader());
+    }
+
+    fn run(&mut self, (events, player, position, mut score): Self::SystemData) {
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            match *event {
+                GameEvent::EnemyKilled { score: value, killer: Some(killer), .. } => if let Some(score) = score.get_mut(killer) {
+                    score.0 += value;
+                },
+                GameEvent::BulletGrazed { player } => if let Some(score) = score.get_mut(player) {
+                    score.0 += GRAZE_SCORE;
+                },
+                GameEvent::BulletConverted { player, score: value } => if let Some(score) = score.get_mut(player) {
+                    score.0 += value;
+                },
+                GameEvent::ItemCollected { player, item: Pickup::Points(value) } => {
+                    if let (Some(score), Some(pos)) = (score.get_mut(player), position.get(player)) {
+                        score.0 += item_points(value, pos.0.y);
+                    }
+                },
+                GameEvent::SpellCardCaptured { bonus: value } | GameEvent::WaveBonus { score: value } => {
+                    (&mut score, &player).join().for_each(|(score, _)| score.0 += value);
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PlaySounds {
+    reader: Option<ReaderId<GameEvent>>,
+}
+
+impl<'a> System<'a> for PlaySounds {
+    type SystemData = (Read<'a, GameEvents>, Read<'a, Audio>);
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<GameEvents>().register_reader());
+    }
+
+    fn run(&mut self, (events, audio): Self::SystemData) {
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            let sound = match event {
+                GameEvent::EnemyKilled { .. } | GameEvent::BossPhaseChanged { .. } => Sound::Explosion,
+                GameEvent::PlayerHit { .. } => Sound::Hit,
+                GameEvent::BombUsed { .. } => Sound::Bomb,
+                GameEvent::ItemCollected { .. } => Sound::Pickup,
+                GameEvent::BulletGrazed { .. } => Sound::Graze,
+                GameEvent::ShotFired { .. } => Sound::Shot,
+                GameEvent::StageFinished { .. } | GameEvent::PathEnded { .. } |
+                GameEvent::BulletConverted { .. } | GameEvent::SpellCardCaptured { .. } | GameEvent::WaveBonus { .. } => continue,
+            };
+
+            audio.play(sound);
+        }
+    }
+}
+
 pub struct FinishStage;
 
 impl<'a> System<'a> for FinishStage {
     type SystemData = (
-        Entities<'a>, Read<'a, LazyUpdate>, Write<'a, Mode>, Read<'a, GameTime>,
-        ReadStorage<'a, Position>, ReadStorage<'a, Enemy>, ReadStorage<'a, Player>, ReadStorage<'a, Boss>);
+        Entities<'a>, Read<'a, LazyUpdate>, Write<'a, Mode>, Read<'a, GameTime>, Write<'a, ActiveSpellCard>, Write<'a, CaptureHistory>,
+        ReadStorage<'a, Position>, ReadStorage<'a, Enemy>, ReadStorage<'a, Player>, ReadStorage<'a, Boss>, ReadStorage<'a, SpellCardName>,
+        ReadStorage<'a, Score>, Write<'a, GameEvents>, Read<'a, Difficulty>,
+        Read<'a, Archetypes>, Write<'a, BossRush>, Write<'a, BossRushRecords>, Read<'a, Practice>, Write<'a, HighScores>,
+    );
 
-    fn run(&mut self, (entities, updater, mut mode, time, pos, enemy, player, boss): Self::SystemData) {
-        if let Mode::Playing { ref mut state, stage, multiplayer } = *mode {
+    fn run(&mut self, (entities, updater, mut mode, time, mut spell_card, mut history, pos, enemy, player, boss, names, score, mut events, difficulty, archetypes, mut boss_rush, mut records, practice, mut high_scores): Self::SystemData) {
+        if let Mode::Playing { ref mut state, ref mut stage, multiplayer, bomb } = *mode {
             match state {
                 PlayingState::Playing => {
                     if (&player).join().count() == 0 {
                         *state = PlayingState::Lost { at: time.total_time };
+                        events.single_write(GameEvent::StageFinished { stage: *stage, won: false, difficulty: *difficulty });
                     }
     
                     if (&boss).join().count() == 0 {
+                        finish_spell_card(&mut spell_card, &mut history, &mut events, &entities, &names, true, practice.active);
+
                         for (entity, pos, _) in (&entities, &pos, &enemy).join() {
                             build_explosion(&updater, &entities, pos.0, time.total_time);
                             entities.delete(entity).unwrap();
                         }
-    
-                        *state = PlayingState::Won { at: time.total_time };
+
+                        let next_boss = if boss_rush.active { crate::stages::BOSS_RUSH.get(boss_rush.boss + 1) } else { None };
+
+                        if let Some(&next_boss) = next_boss {
+                            boss_rush.boss += 1;
+                            *stage = next_boss;
+                            let setup = StageSetup { multiplayer, bomb, difficulty: *difficulty, start: StageStart::Time(0.0) };
+                            crate::stages::next_rush_boss(&entities, &updater, &archetypes, next_boss, setup, time.total_time);
+                        } else {
+                            *state = PlayingState::Won { at: time.total_time };
+                            events.single_write(GameEvent::StageFinished { stage: *stage, won: true, difficulty: *difficulty });
+                        }
                     }
                 }
                 PlayingState::Won { at: won_at } => if *won_at + 1.0 < time.total_time {
-                    *mode = Mode::StageComplete { stage, selected: 0, multiplayer }
+                    let total_score = (&score).join().map(|player_score| player_score.0).sum();
+
+                    *mode = if boss_rush.active {
+                        boss_rush.active = false;
+                        let record = BossRushRecord { score: total_score, time: *won_at };
+                        Mode::BossRushComplete {
+                            selected: 0, score: total_score, time: *won_at, difficulty: *difficulty, place: records.add(record, *difficulty),
+                        }
+                    } else {
+                        // Practice runs can start part way through, so they don't set high scores
+                        let high_score = if practice.active {
+                            high_scores.get(*stage, *difficulty).unwrap_or(0)
+                        } else {
+                            high_scores.add(*stage, *difficulty, total_score)
+                        };
+
+                        Mode::StageComplete { stage: *stage, selected: 0, multiplayer, bomb, score: total_score, high_score }
+                    }
                 }
                 PlayingState::Lost { at: lost_at } => if *lost_at + 1.0 < time.total_time {
                     *mode = Mode::StageLost { selected: 0 }
@@ -449,6 +926,21 @@ impl<'a> System<'a> for FinishStage {
     }
 }
 
+fn build_item(entities: &Entities, updater: &LazyUpdate, pos: Vector2<f32>, item: Pickup) {
+    let mut rng = rand::thread_rng();
+    // Spread out items that are dropped together
+    let offset = Vector2::new(rng.gen_range(-10.0, 10.0), rng.gen_range(-10.0, 10.0));
+
+    updater.create_entity(entities)
+        .with(Position(pos + offset))
+        .with(item)
+        .with(Falling { speed: 0.0, down: true })
+        .with(Image::from(item.image()))
+        .with(Hitbox(Vector2::new(50.0, 50.0)))
+        .with(DieOffscreen)
+        .build();
+}
+
 fn build_explosion(updater: &specs::world::LazyUpdate, entities: &Entities, pos: Vector2<f32>, time: f32) {
     updater.create_entity(&entities)
         .with(Position(pos))
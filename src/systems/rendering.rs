@@ -1,5 +1,7 @@
 use specs::prelude::*;
+use specs::shrev::ReaderId;
 use cgmath::Vector2;
+use rand::Rng;
 use crate::{WIDTH, HEIGHT, resources::*, components::*, renderer::BufferRenderer as Renderer, graphics::Image as GraphicsImage};
 
 pub struct RepeatBackgroundLayers;
@@ -23,11 +25,11 @@ pub struct RenderSprite { sorted_bgs: Vec<(Vector2<f32>, Image, u32)> }
 impl<'a> System<'a> for RenderSprite {
     type SystemData = (
         ReadStorage<'a, Position>, ReadStorage<'a, Image>, ReadStorage<'a, Invulnerability>, ReadStorage<'a, FrozenUntil>,
-        ReadStorage<'a, BackgroundLayer>, ReadStorage<'a, ColourOverlay>, ReadStorage<'a, Rotation>,
+        ReadStorage<'a, BackgroundLayer>, ReadStorage<'a, ColourOverlay>, ReadStorage<'a, Rotation>, ReadStorage<'a, CollidesWithBomb>,
         Read<'a, GameTime>, Write<'a, Renderer>
     );
 
-    fn run(&mut self, (pos, image, invul, frozen, bg, overlay, rot, time, mut renderer): Self::SystemData) {
+    fn run(&mut self, (pos, image, invul, frozen, bg, overlay, rot, enemy_bullet, time, mut renderer): Self::SystemData) {
         self.sorted_bgs.extend((&pos, &image, &bg).join().map(|(pos, image, bg)| (pos.0, *image, bg.depth)));
         self.sorted_bgs.sort_unstable_by_key(|&(_, _, depth)| depth);
 
@@ -35,7 +37,7 @@ impl<'a> System<'a> for RenderSprite {
             renderer.render_sprite(image, pos, 0.0, [0.0; 4]);
         }
 
-        for (pos, image, invul, overlay, rotation, _, _) in (&pos, &image, invul.maybe(), overlay.maybe(), rot.maybe(), !&frozen, !&bg).join() {
+        for (pos, image, invul, overlay, rotation, enemy_bullet, _, _) in (&pos, &image, invul.maybe(), overlay.maybe(), rot.maybe(), enemy_bullet.maybe(), !&frozen, !&bg).join() {
             let overlay = overlay
                 .map(|overlay| overlay.0)
                 .unwrap_or_else(|| {
@@ -50,7 +52,11 @@ impl<'a> System<'a> for RenderSprite {
 
             let rotation = rotation.map(|rotation| rotation.0).unwrap_or(0.0);
             
-            renderer.render_sprite(*image, pos.0, rotation, overlay);
+            if enemy_bullet.is_some() {
+                renderer.render_glowing_sprite(*image, pos.0, rotation, overlay);
+            } else {
+                renderer.render_sprite(*image, pos.0, rotation, overlay);
+            }
         }
     }
 }
@@ -74,6 +80,20 @@ impl<'a> System<'a> for RenderHitboxes {
     }
 }
 
+pub struct RenderFocusIndicators;
+
+impl<'a> System<'a> for RenderFocusIndicators {
+    type SystemData = (ReadStorage<'a, Position>, ReadStorage<'a, Player>, Write<'a, Renderer>, Read<'a, ControlsState>, Read<'a, Settings>);
+
+    fn run(&mut self, (pos, player, mut renderer, ctrl_state, settings): Self::SystemData) {
+        for (pos, player) in (&pos, &player).join() {
+            if settings.always_show_hitbox || ctrl_state.get(*player).slow_movement.pressed {
+                renderer.render_sprite(Image::from(GraphicsImage::Focus), pos.0, 0.0, [0.0; 4]);
+            }
+        }
+    }
+}
+
 pub struct RenderPauseBackground;
 
 impl<'a> System<'a> for RenderPauseBackground {
@@ -99,10 +119,14 @@ impl<'a> System<'a> for RenderText {
 pub struct RenderUI;
 
 impl<'a> System<'a> for RenderUI {
-    type SystemData = (ReadStorage<'a, Player>, ReadStorage<'a, Health>, ReadStorage<'a, PowerBar>, ReadStorage<'a, Boss>, ReadStorage<'a, FrozenUntil>, Write<'a, Renderer>);
+    type SystemData = (
+        ReadStorage<'a, Player>, ReadStorage<'a, Health>, ReadStorage<'a, PowerBar>, ReadStorage<'a, Score>,
+        ReadStorage<'a, Boss>, ReadStorage<'a, FrozenUntil>, Write<'a, Renderer>, Read<'a, GameTime>, Read<'a, ActiveSpellCard>,
+        Read<'a, BossRush>,
+    );
 
-    fn run(&mut self, (player, health, bar, boss, frozen, mut renderer): Self::SystemData) {
-        let mut join = (&player, &health, &bar).join().map(|(_, health, bar)| (health.0, bar));
+    fn run(&mut self, (player, health, bar, score, boss, frozen, mut renderer, time, spell_card, boss_rush): Self::SystemData) {
+        let mut join = (&player, &health, &bar, &score).join().map(|(_, health, bar, score)| (health.0, bar, score.0));
 
         const MAX_BAR_HEIGHT: f32 = 32.0;
         const BAR_WIDTH: f32 = 16.0;
@@ -110,13 +134,19 @@ impl<'a> System<'a> for RenderUI {
         const PADDED_MAX_BAR_HEIGHT: f32 = MAX_BAR_HEIGHT - PADDING;
         const BAR_DIMENSIONS: Vector2<f32> = Vector2::new(BAR_WIDTH, MAX_BAR_HEIGHT);
 
-        if let Some((health, bar)) = join.next() {
+        if let Some((health, bar, score)) = join.next() {
             renderer.render_text(&Text {
                 text: health.to_string(),
                 font: 1,
                 layout: wgpu_glyph::Layout::default().v_align(wgpu_glyph::VerticalAlign::Center)
             }, Vector2::new(60.0, HEIGHT - 30.0), [1.0; 4]);
 
+            renderer.render_text(&Text {
+                text: score.to_string(),
+                font: 1,
+                layout: wgpu_glyph::Layout::default().v_align(wgpu_glyph::VerticalAlign::Center)
+            }, Vector2::new(14.0, HEIGHT - 64.0), [1.0; 4]);
+
             renderer.render_sprite(Image::from(GraphicsImage::Portrait), Vector2::new(30.0, HEIGHT - 30.0), 0.0, [0.0; 4]);
 
             let perc = bar.perc();
@@ -126,13 +156,19 @@ impl<'a> System<'a> for RenderUI {
             renderer.render_box(Vector2::new(80.0, HEIGHT - 30.0 + missing), Vector2::new(BAR_WIDTH - PADDING, perc * PADDED_MAX_BAR_HEIGHT), [0.5, 0.125, 0.125, 1.0]);
         }
 
-        if let Some((health, bar)) = join.next() {
+        if let Some((health, bar, score)) = join.next() {
             renderer.render_text(&Text {
                 text: health.to_string(),
                 font: 1,
                 layout: wgpu_glyph::Layout::default().v_align(wgpu_glyph::VerticalAlign::Center).h_align(wgpu_glyph::HorizontalAlign::Right)
             }, Vector2::new(WIDTH - 60.0, HEIGHT - 30.0), [1.0; 4]);
 
+            renderer.render_text(&Text {
+                text: score.to_string(),
+                font: 1,
+                layout: wgpu_glyph::Layout::default().v_align(wgpu_glyph::VerticalAlign::Center).h_align(wgpu_glyph::HorizontalAlign::Right)
+            }, Vector2::new(WIDTH - 14.0, HEIGHT - 64.0), [1.0; 4]);
+
             renderer.render_sprite(Image::from(GraphicsImage::Portrait), Vector2::new(WIDTH - 30.0, HEIGHT - 30.0), 0.0, [0.0; 4]);
 
             let perc = bar.perc();
@@ -142,12 +178,122 @@ impl<'a> System<'a> for RenderUI {
             renderer.render_box(Vector2::new(WIDTH - 80.0, HEIGHT - 30.0 + missing), Vector2::new(BAR_WIDTH - PADDING, perc * PADDED_MAX_BAR_HEIGHT), [0.5, 0.125, 0.125, 1.0]);
         }
 
+        if boss_rush.active {
+            renderer.render_text(&Text {
+                text: format_time(time.total_time),
+                font: 1,
+                layout: wgpu_glyph::Layout::default().v_align(wgpu_glyph::VerticalAlign::Center).h_align(wgpu_glyph::HorizontalAlign::Center)
+            }, Vector2::new(WIDTH / 2.0, HEIGHT - 30.0), [1.0; 4]);
+        }
+
         let mut offset = 10.0;
 
         for (health, boss, _) in (&health, &boss, !&frozen).join() {
-            let width = (WIDTH - 20.0) * health.0 as f32 / boss.max_health as f32;
-            renderer.render_sprite_with_dimensions(Image::from(GraphicsImage::BossHealthBar), Vector2::new(WIDTH / 2.0, offset), Vector2::new(width, 10.0), 0.0, [0.0; 4]);
-            offset += 15.0
+            let width = (WIDTH - 20.0) * boss.phase_health_perc(health.0);
+            let overlay = if boss.invulnerable(time.total_time) { [1.0, 1.0, 1.0, 0.5] } else { [0.0; 4] };
+            renderer.render_sprite_with_dimensions(Image::from(GraphicsImage::BossHealthBar), Vector2::new(WIDTH / 2.0, offset), Vector2::new(width, 10.0), 0.0, overlay);
+
+            // Show how many phases are left
+            for i in 0 .. boss.phases_remaining() {
+                renderer.render_box(Vector2::new(WIDTH - 12.0 - i as f32 * 8.0, offset + 10.0), Vector2::new(4.0, 4.0), [1.0; 4]);
+            }
+
+            offset += 20.0
+        }
+
+        if let Some(attempt) = &spell_card.0 {
+            renderer.render_text(&Text {
+                text: format!("{:.1}", attempt.time_remaining(time.total_time)),
+                font: 1,
+                layout: wgpu_glyph::Layout::default()
+            }, Vector2::new(10.0, offset), [1.0; 4]);
+        }
+    }
+}
+
+const SHAKE_DURATION: f32 = 0.4;
+
+#[derive(Default)]
+pub struct ShakeScreen {
+    reader: Option<ReaderId<GameEvent>>,
+    // Counted down each tick instead of storing an end time, as the game time resets between stages.
+    remaining: f32,
+    strength: f32,
+}
+
+impl<'a> System<'a> for ShakeScreen {
+    type SystemData = (Read<'a, GameEvents>, Read<'a, Settings>, Write<'a, Renderer>);
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<GameEvents>().register_reader());
+    }
+
+    fn run(&mut self, (events, settings, mut renderer): Self::SystemData) {
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            let strength = match event {
+                GameEvent::PlayerHit { .. } => 6.0,
+                GameEvent::BombUsed { .. } => 3.0,
+                _ => continue,
+            };
+
+            self.remaining = SHAKE_DURATION;
+            self.strength = strength;
+        }
+
+        if self.remaining <= 0.0 {
+            return;
+        }
+
+        self.remaining -= 1.0 / 60.0;
+
+        if settings.screen_shake {
+            let fade = (self.remaining / SHAKE_DURATION).max(0.0).min(1.0);
+            let mut rng = rand::thread_rng();
+            let shake = Vector2::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)) * self.strength * fade;
+            renderer.set_shake(shake);
+        }
+    }
+}
+
+const FLASH_DURATION: f32 = 0.3;
+const FLASH_ALPHA: f32 = 0.6;
+
+// Flashes the screen when a bomb is used, and for the whole of a flash bomb.
+#[derive(Default)]
+pub struct FlashScreen {
+    reader: Option<ReaderId<GameEvent>>,
+    // Counted down each tick, like the screen shake.
+    remaining: f32,
+    duration: f32,
+    alpha: f32,
+}
+
+impl<'a> System<'a> for FlashScreen {
+    type SystemData = (Read<'a, GameEvents>, Write<'a, Renderer>);
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<GameEvents>().register_reader());
+    }
+
+    fn run(&mut self, (events, mut renderer): Self::SystemData) {
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            if let GameEvent::BombUsed { bomb, .. } = event {
+                let (duration, alpha) = match bomb {
+                    BombType::Flash => (bomb.duration(), 1.0),
+                    _ => (FLASH_DURATION, FLASH_ALPHA),
+                };
+
+                self.remaining = duration;
+                self.duration = duration;
+                self.alpha = alpha;
+            }
+        }
+
+        if self.remaining > 0.0 {
+            self.remaining -= 1.0 / 60.0;
+            renderer.set_flash((self.remaining / self.duration * self.alpha).max(0.0).min(self.alpha));
         }
     }
 }
@@ -155,10 +301,10 @@ impl<'a> System<'a> for RenderUI {
 pub struct RenderMenu;
 
 impl<'a> System<'a> for RenderMenu {
-    type SystemData = (Write<'a, Renderer>, Write<'a, Mode>, Read<'a, ControlsState>);
+    type SystemData = (Write<'a, Renderer>, Write<'a, Mode>, Read<'a, ControlsState>, Read<'a, Settings>, Read<'a, BossRushRecords>);
 
-    fn run(&mut self, (mut renderer, mut mode, ctrl_state): Self::SystemData) {
-        if let Some(menu) = mode.as_menu(&ctrl_state) {
+    fn run(&mut self, (mut renderer, mut mode, ctrl_state, settings, records): Self::SystemData) {
+        if let Some(menu) = mode.as_menu(&ctrl_state, &settings, &records) {
             renderer.render_text(&Text::title(&menu.title), Vector2::new(WIDTH / 2.0, 40.0), [1.0; 4]);
 
             let mut x = 190.0;
@@ -208,11 +354,26 @@ impl<'a> System<'a> for ExplosionImages {
 pub struct RenderBombs;
 
 impl<'a> System<'a> for RenderBombs {
-    type SystemData = (Write<'a, Renderer>, ReadStorage<'a, Position>, ReadStorage<'a, Circle>);
-
-    fn run(&mut self, (mut renderer, pos, circle): Self::SystemData) {
-        for (pos, circle) in (&pos, &circle).join() {
-            renderer.render_circle(pos.0, circle.radius);
+    type SystemData = (Write<'a, Renderer>, ReadStorage<'a, Position>, ReadStorage<'a, Bomb>);
+
+    fn run(&mut self, (mut renderer, pos, bomb): Self::SystemData) {
+        for (pos, bomb) in (&pos, &bomb).join() {
+            match bomb.kind {
+                BombKind::Circle { radius } => renderer.render_circle(pos.0, radius),
+                // Drawn by `FlashScreen`, so that it can be turned off
+                BombKind::Flash => {},
+                BombKind::Beams { rotation } => for rotation in Bomb::beam_rotations(rotation) {
+                    let direction = Vector2::new(rotation.cos(), rotation.sin());
+                    renderer.render_rotated_box(
+                        pos.0 + direction * Bomb::BEAM_LENGTH / 2.0,
+                        Vector2::new(Bomb::BEAM_WIDTH, Bomb::BEAM_LENGTH),
+                        direction.x.atan2(-direction.y),
+                        [1.0, 1.0, 1.0, 0.5]
+                    );
+                },
+                BombKind::Spirit { .. } => renderer.render_sprite(Image::from(GraphicsImage::Orb), pos.0, 0.0, [0.5, 0.75, 1.0, 0.75]),
+                BombKind::Shield => renderer.render_circle(pos.0, Bomb::SHIELD_RADIUS),
+            }
         }
     }
 }
@@ -0,0 +1,60 @@
+// Runs stages without a window to check that the game keeps ticking along.
+
+use specs::prelude::*;
+use hectic_rs::{Game, resources::*, stages::StageSetup, components::{Player, Enemy, FrozenUntil, BombType}};
+
+fn start(multiplayer: bool) -> Game {
+    let mut game = Game::headless();
+    game.start_stage(Stage::One, StageSetup {
+        multiplayer, bomb: BombType::Circle, difficulty: Difficulty::Normal, start: StageStart::Time(0.0),
+    });
+    game
+}
+
+fn time(game: &Game) -> f32 {
+    game.world.fetch::<GameTime>().total_time
+}
+
+fn players(game: &Game) -> usize {
+    game.world.read_storage::<Player>().join().count()
+}
+
+// Enemies (and their bullets) that have spawned in.
+fn active_enemies(game: &Game) -> usize {
+    (&game.world.read_storage::<Enemy>(), !&game.world.read_storage::<FrozenUntil>()).join().count()
+}
+
+#[test]
+fn stage_starts_playing() {
+    let game = start(false);
+
+    assert!(matches!(game.mode(), Mode::Playing { stage: Stage::One, state: PlayingState::Playing, multiplayer: false, .. }));
+    assert_eq!(time(&game), 0.0);
+    assert_eq!(players(&game), 1);
+    // Everything in the stage is created at the start, frozen until it's due
+    assert!(game.world.read_storage::<Enemy>().join().count() > 0);
+    assert_eq!(active_enemies(&game), 0);
+}
+
+#[test]
+fn stepping_advances_the_stage() {
+    let mut game = start(false);
+
+    for _ in 0 .. 120 {
+        game.step();
+    }
+
+    assert!(matches!(game.mode(), Mode::Playing { stage: Stage::One, state: PlayingState::Playing, .. }));
+    assert!((time(&game) - 2.0).abs() < 0.001);
+    assert_eq!(players(&game), 1);
+    assert!(active_enemies(&game) > 0);
+}
+
+#[test]
+fn multiplayer_has_two_players() {
+    let mut game = start(true);
+    game.step();
+
+    assert!(matches!(game.mode(), Mode::Playing { multiplayer: true, .. }));
+    assert_eq!(players(&game), 2);
+}
@@ -0,0 +1,104 @@
+// Has the bot play every stage, and fails if any of them can't be survived.
+// These take a while, so run them with `cargo test --release -- --ignored`.
+
+use specs::prelude::*;
+use hectic_rs::{Game, resources::*, stages::StageSetup, components::{Boss, FrozenUntil, BombType}};
+
+// Ten minutes, which is much longer than any stage should take.
+const MAX_TICKS: u32 = 60 * 60 * 10;
+
+#[derive(Debug)]
+struct Playthrough {
+    stage: Stage,
+    difficulty: Difficulty,
+    won: bool,
+    // How many times the bot was hit. Hits cancelled by a bomb aren't counted.
+    deaths: u32,
+    bombs: u32,
+    // Seconds from the boss appearing to it being beaten.
+    boss_time_to_kill: Option<f32>,
+    duration: f32,
+}
+
+impl Playthrough {
+    fn run(stage: Stage, bomb: BombType, difficulty: Difficulty) -> Self {
+        let mut game = Game::headless();
+        game.start_stage(stage, StageSetup { multiplayer: false, bomb, difficulty, start: StageStart::Time(0.0) });
+        game.add_bots();
+
+        let mut reader = game.world.fetch_mut::<GameEvents>().register_reader();
+        let start = game.world.fetch::<GameTime>().total_time;
+
+        let mut playthrough = Self {
+            stage, difficulty, won: false, deaths: 0, bombs: 0, boss_time_to_kill: None, duration: 0.0,
+        };
+        let mut boss_appeared = None;
+
+        for _ in 0 .. MAX_TICKS {
+            game.step();
+
+            let time = game.world.fetch::<GameTime>().total_time;
+            playthrough.duration = time - start;
+
+            if boss_appeared.is_none() {
+                let boss = game.world.read_storage::<Boss>();
+                let frozen = game.world.read_storage::<FrozenUntil>();
+
+                if (&boss, !&frozen).join().next().is_some() {
+                    boss_appeared = Some(time);
+                }
+            }
+
+            let mut finished = false;
+
+            for event in game.world.fetch::<GameEvents>().read(&mut reader) {
+                match *event {
+                    GameEvent::PlayerHit { .. } => playthrough.deaths += 1,
+                    GameEvent::BombUsed { .. } => playthrough.bombs += 1,
+                    GameEvent::StageFinished { won, .. } => {
+                        playthrough.won = won;
+                        finished = true;
+                    },
+                    _ => {}
+                }
+            }
+
+            if finished {
+                if playthrough.won {
+                    playthrough.boss_time_to_kill = boss_appeared.map(|appeared| time - appeared);
+                }
+
+                break;
+            }
+        }
+
+        playthrough
+    }
+}
+
+fn survive(stage: Stage) {
+    let playthrough = Playthrough::run(stage, BombType::Circle, Difficulty::Normal);
+
+    println!(
+        "Stage {:?} ({:?}): {} after {:.1}s, {} deaths, {} bombs",
+        playthrough.stage, playthrough.difficulty, if playthrough.won { "won" } else { "lost" }, playthrough.duration, playthrough.deaths, playthrough.bombs,
+    );
+
+    if let Some(time_to_kill) = playthrough.boss_time_to_kill {
+        println!("  Boss beaten in {:.1}s", time_to_kill);
+    }
+
+    assert!(playthrough.won, "{:?}", playthrough);
+}
+
+#[test]
+#[ignore]
+fn stage_one() {
+    survive(Stage::One);
+}
+
+#[test]
+#[ignore]
+fn stage_two() {
+    survive(Stage::Two);
+}
@@ -0,0 +1,81 @@
+// Draws frames with the software renderer and checks individual pixels.
+
+use specs::prelude::*;
+use cgmath::Vector2;
+use hectic_rs::{Game, audio::Audio, systems, resources::*, stages::StageSetup, components::BombType};
+use hectic_rs::renderer::{BufferRenderer, software::SoftwareRenderer};
+
+const WIDTH: u32 = 480;
+const HEIGHT: u32 = 640;
+const BORDER: [u8; 4] = [128, 32, 32, 255];
+const WHITE: [u8; 4] = [255; 4];
+
+fn render(renderer: &BufferRenderer) -> image::RgbaImage {
+    SoftwareRenderer::default().render(renderer)
+}
+
+fn pixel(image: &image::RgbaImage, x: u32, y: u32) -> [u8; 4] {
+    image.get_pixel(x, y).0
+}
+
+#[test]
+fn boxes_are_drawn_in_their_colour() {
+    let mut renderer = BufferRenderer::new(WIDTH, HEIGHT);
+    renderer.render_box(Vector2::new(100.0, 100.0), Vector2::new(20.0, 20.0), [0.0, 1.0, 0.0, 1.0]);
+    let image = render(&renderer);
+
+    assert_eq!(image.dimensions(), (WIDTH, HEIGHT));
+    assert_eq!(pixel(&image, 100, 100), [0, 255, 0, 255]);
+    assert_eq!(pixel(&image, 50, 50), BORDER);
+}
+
+#[test]
+fn borders_cover_the_sides_of_wide_windows() {
+    let mut renderer = BufferRenderer::new(WIDTH * 2, HEIGHT);
+    // Half of this is outside the playfield
+    renderer.render_box(Vector2::new(0.0, 320.0), Vector2::new(100.0, 100.0), [0.0, 1.0, 0.0, 1.0]);
+    let image = render(&renderer);
+
+    // The playfield is centred, starting a quarter of the way across
+    let left = WIDTH / 2;
+    assert_eq!(pixel(&image, left - 10, 320), BORDER);
+    assert_eq!(pixel(&image, left + 20, 320), [0, 255, 0, 255]);
+}
+
+#[test]
+fn flash_blends_over_the_playfield() {
+    let mut renderer = BufferRenderer::new(WIDTH, HEIGHT);
+    renderer.set_flash(0.5);
+    assert_eq!(pixel(&render(&renderer), 240, 320), [191, 143, 143, 255]);
+
+    renderer.clear();
+    assert_eq!(pixel(&render(&renderer), 240, 320), BORDER);
+}
+
+#[test]
+fn main_menu_has_text() {
+    let mut game = Game::new(Some(BufferRenderer::new(WIDTH, HEIGHT)), Audio::default());
+    game.step();
+    let image = render(&game.world.fetch::<BufferRenderer>());
+
+    assert!(image.pixels().any(|pixel| pixel.0 == WHITE));
+}
+
+#[test]
+fn hud_draws_the_power_bar() {
+    let mut game = Game::new(Some(BufferRenderer::new(WIDTH, HEIGHT)), Audio::default());
+    game.start_stage(Stage::One, StageSetup {
+        multiplayer: false, bomb: BombType::Circle, difficulty: Difficulty::Normal, start: StageStart::Time(0.0),
+    });
+    game.step();
+
+    // Only the HUD, without the sprites underneath it
+    game.world.fetch_mut::<BufferRenderer>().clear();
+    systems::RenderUI.run_now(&game.world);
+    let image = render(&game.world.fetch::<BufferRenderer>());
+
+    // The edge of the bar's black outline, beside the fill
+    assert_eq!(pixel(&image, 73, HEIGHT - 30), [0, 0, 0, 255]);
+    // Score and lives
+    assert!((HEIGHT - 80 .. HEIGHT).any(|y| (0 .. 70).any(|x| pixel(&image, x, y) == WHITE)));
+}